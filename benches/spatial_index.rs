@@ -0,0 +1,98 @@
+//! Compares the spatial index backends at different swarm sizes.
+//! Run with `cargo bench --bench spatial_index`.
+
+use boids::{spatial::SpatialIndexConfig, vec::Vec2};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+const BACKENDS: [(&str, SpatialIndexConfig); 2] = [
+    ("kd_tree", SpatialIndexConfig::KdTree),
+    ("grid", SpatialIndexConfig::Grid { cell_size: 10. }),
+];
+
+/// Scatter bois about an arena big enough to keep the density the same as the default scenario
+fn random_positions(rng: &mut impl Rng, n: usize) -> Vec<Vec2> {
+    let arena_radius = 100. * (n as f32 / 1000.).sqrt();
+    (0..n)
+        .map(|_| {
+            Vec2::new(
+                rng.gen_range(-arena_radius..arena_radius),
+                rng.gen_range(-arena_radius..arena_radius),
+            )
+        })
+        .collect()
+}
+
+/// Move everything a small amount, like a single tick would
+fn jiggle(rng: &mut impl Rng, positions: &[Vec2]) -> Vec<Vec2> {
+    positions
+        .iter()
+        .map(|pos| {
+            pos.add(&Vec2::new(
+                rng.gen_range(-0.1..0.1),
+                rng.gen_range(-0.1..0.1),
+            ))
+        })
+        .collect()
+}
+
+fn bench_spatial_index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spatial_index");
+    group.sample_size(10);
+
+    for n in [1_000, 10_000, 100_000] {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let positions = random_positions(&mut rng, n);
+        let moved = jiggle(&mut rng, &positions);
+
+        for (name, backend) in BACKENDS {
+            // Updating after a tick's worth of movement
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}/update", name), n),
+                &n,
+                |b, _| {
+                    let mut index = backend.build(&positions);
+                    let mut flip = false;
+                    b.iter(|| {
+                        index.update(if flip { &positions } else { &moved });
+                        flip = !flip;
+                    });
+                },
+            );
+
+            // Every boi looking around itself, like the decision phase
+            let index = backend.build(&positions);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}/within", name), n),
+                &n,
+                |b, _| {
+                    b.iter(|| {
+                        positions
+                            .iter()
+                            .map(|pos| index.within(pos, 6.).len())
+                            .sum::<usize>()
+                    });
+                },
+            );
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}/nearest", name), n),
+                &n,
+                |b, _| {
+                    b.iter(|| {
+                        positions
+                            .iter()
+                            .map(|pos| index.nearest(pos, 8).len())
+                            .sum::<usize>()
+                    });
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_spatial_index);
+criterion_main!(benches);
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    shape::{Shape, ShapeConfig, ShapeIndex},
+    species::SpeciesId,
+    vec::Vec2,
+};
+
+/// A region of the arena as it's written in the scenario file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BiomeConfig {
+    pub name: String,
+    pub colour: [u8; 3], // RGB, drawn as the background
+    pub region: ShapeConfig,
+    #[serde(default)]
+    pub modifiers: HashMap<String, Modifiers>, // By species name, anyone not listed is unaffected
+}
+
+/// How much a biome scales a species' traits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Modifiers {
+    pub speed: f32,
+    pub vision: f32,
+    pub turning_speed: f32,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self {
+            speed: 1.,
+            vision: 1.,
+            turning_speed: 1.,
+        }
+    }
+}
+
+pub struct Biome {
+    pub name: String,
+    pub colour: [u8; 3],
+    modifiers: Vec<Modifiers>, // By species
+}
+
+/// All the biomes in the world. Where they overlap, the one defined last wins.
+pub struct Biomes {
+    biomes: Vec<Biome>,
+    index: ShapeIndex, // Region of each biome, in the same order
+}
+
+impl Biomes {
+    /// Assumes the config has already been validated, so every species name is a real one
+    pub fn from_config(configs: &[BiomeConfig], species: &[String]) -> Self {
+        let biomes = configs
+            .iter()
+            .map(|config| Biome {
+                name: config.name.clone(),
+                colour: config.colour,
+                modifiers: species
+                    .iter()
+                    .map(|name| config.modifiers.get(name).copied().unwrap_or_default())
+                    .collect(),
+            })
+            .collect();
+        let index = ShapeIndex::new(configs.iter().map(|c| Shape::from(&c.region)).collect());
+
+        Self { biomes, index }
+    }
+
+    /// Each biome along with its region
+    pub fn iter(&self) -> impl Iterator<Item = (&Biome, &Shape)> {
+        self.biomes.iter().zip(self.index.shapes())
+    }
+
+    /// Which biome a point is in, if any
+    pub fn at(&self, point: &Vec2) -> Option<&Biome> {
+        self.index
+            .containing(point)
+            .last()
+            .map(|(i, _)| &self.biomes[i])
+    }
+
+    /// How traits are scaled for a species at a point
+    pub fn modifiers(&self, point: &Vec2, species: SpeciesId) -> Modifiers {
+        self.at(point)
+            .map(|biome| biome.modifiers[species])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn overlapping_biomes() {
+        let config = Config::from_toml(
+            r#"
+            [[biomes]]
+            name = "marsh"
+            colour = [0, 0, 0]
+            region = { shape = "circle", centre = [0.0, 0.0], radius = 10.0 }
+            modifiers.prey = { speed = 0.5 }
+
+            [[biomes]]
+            name = "forest"
+            colour = [0, 0, 0]
+            region = { shape = "polygon", points = [[0.0, 0.0], [20.0, 0.0], [20.0, 20.0], [0.0, 20.0]] }
+            modifiers.predator = { vision = 0.25 }
+            "#,
+        )
+        .unwrap();
+        let names = config
+            .species
+            .iter()
+            .map(|s| s.name.clone())
+            .collect::<Vec<_>>();
+        let biomes = Biomes::from_config(&config.biomes, &names);
+
+        // Just the marsh
+        let marsh = Vec2::new(-5., 0.);
+        assert_eq!(biomes.at(&marsh).unwrap().name, "marsh");
+        assert_eq!(biomes.modifiers(&marsh, 1).speed, 0.5);
+        assert_eq!(biomes.modifiers(&marsh, 0), Modifiers::default());
+
+        // The forest is on top
+        let both = Vec2::new(5., 5.);
+        assert_eq!(biomes.at(&both).unwrap().name, "forest");
+        assert_eq!(biomes.modifiers(&both, 0).vision, 0.25);
+        assert_eq!(biomes.modifiers(&both, 1), Modifiers::default());
+
+        assert!(biomes.at(&Vec2::new(-50., 0.)).is_none());
+    }
+}
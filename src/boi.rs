@@ -1,24 +1,105 @@
-use std::{f32::consts::PI, ops::Sub};
+use std::{
+    f32::consts::{PI, TAU},
+    ops::Sub,
+};
 
-use geo_index::kdtree::KDTreeIndex;
-use rand::{prelude::Distribution, seq::SliceRandom, Rng};
+use rand::{prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
 
-use crate::{entity::EntityTemplate, strategy::Strategy, vec::Vec2};
+use crate::{
+    config::RuleWeights,
+    entity::{EntityId, EntityTemplate},
+    sight,
+    species::SpeciesId,
+    vec::Vec2,
+    world::World,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Species {
-    Predator,
-    Prey,
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Boi {
-    pub species: Species,
+    pub id: EntityId,
+    pub species: SpeciesId,
     pub position: Vec2,
     pub direction: f32, // radians
     pub speed: f32,
     pub vision: f32,
+    pub fov: f32, // Field of view in radians, centred on the direction. TAU sees all round.
     pub turning_speed: f32,
+    pub weights: Weights, // How much it cares about each rule
+    pub energy: f32,      // Starves when this hits zero
+    pub age: f32,         // Seconds since it hatched or was born
+}
+
+/// How much a boi cares about each of the flocking & hunting rules
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Weights<T = f32> {
+    pub separation: T,
+    pub alignment: T,
+    pub cohesion: T,
+    pub attack: T,
+    pub defend: T,
+}
+
+impl<T> Weights<T> {
+    /// Apply `f` to each weight, always in the same order
+    pub fn map<U>(&self, mut f: impl FnMut(&T) -> U) -> Weights<U> {
+        Weights {
+            separation: f(&self.separation),
+            alignment: f(&self.alignment),
+            cohesion: f(&self.cohesion),
+            attack: f(&self.attack),
+            defend: f(&self.defend),
+        }
+    }
+
+    /// Pair up each weight with the matching one from `other`
+    pub fn zip<U>(self, other: Weights<U>) -> Weights<(T, U)> {
+        Weights {
+            separation: (self.separation, other.separation),
+            alignment: (self.alignment, other.alignment),
+            cohesion: (self.cohesion, other.cohesion),
+            attack: (self.attack, other.attack),
+            defend: (self.defend, other.defend),
+        }
+    }
+}
+
+impl Weights {
+    /// Average of a bunch of weights. None if there aren't any.
+    pub fn mean(weights: impl Iterator<Item = Weights>) -> Option<Self> {
+        let (total, count) = weights
+            .fold((Weights::<f32>::default(), 0), |(total, count), weights| {
+                (total.zip(weights).map(|(a, b)| a + b), count + 1)
+            });
+        (count > 0).then(|| total.map(|total: &f32| total / count as f32))
+    }
+
+    /// Everyone caring exactly as much as the scenario's rules say
+    pub fn from_rules(rules: &RuleWeights) -> Self {
+        Self {
+            separation: rules.separation,
+            alignment: rules.alignment,
+            cohesion: rules.cohesion,
+            attack: rules.attack,
+            defend: rules.defend,
+        }
+    }
+}
+
+/// A trait that's either the same for everyone, or different for each boi
+pub enum Sampled<D: Distribution<f32>> {
+    Fixed(f32),
+    From(D),
+}
+
+impl<D: Distribution<f32>> Sampled<D> {
+    fn sample<R: Rng>(&self, rng: &mut R) -> f32 {
+        match self {
+            Sampled::Fixed(value) => *value,
+            Sampled::From(distribution) => distribution.sample(rng),
+        }
+    }
 }
 
 impl Boi {
@@ -29,180 +110,175 @@ impl Boi {
             y: self.direction.sin(),
         }
     }
+
+    /// Everyone within `vision` & our field of view, how far away they are, and whether they're
+    /// hidden from us behind something
+    pub fn look<'a>(&self, world: &'a World, vision: f32) -> Vec<(&'a Boi, f32, bool)> {
+        let falloff = world.config.species[self.species].peripheral_falloff;
+        let around = world
+            // Query the spatial index since it's quicker
+            .boi_index
+            .within(&self.position, vision)
+            .into_iter()
+            // Get the bois based on the spatial query
+            .map(|n| {
+                let boi = world.bois.get(n.index).expect("Got invalid boi index!");
+                (boi, n.distance)
+            })
+            .collect::<Vec<_>>();
+
+        // Anyone around might be in the way, even if we can't see them
+        let bodies = if world.config.occlusion.bois {
+            around.iter().map(|(boi, _)| *boi).collect()
+        } else {
+            vec![]
+        };
+
+        around
+            .iter()
+            // Skip ourselves, & anyone outside our field of view
+            .filter(|(boi, _)| {
+                boi.id != self.id && self.sees(&boi.position.sub(&self.position), vision, falloff)
+            })
+            .map(|(boi, distance)| {
+                let hidden =
+                    world.config.occlusion.enabled() && sight::hidden(world, self, boi, &bodies);
+                (*boi, *distance, hidden)
+            })
+            .collect()
+    }
+
+    /// Whether something `offset` away is in our field of view & within `vision`. Peripheral vision
+    /// reaches less far, down to `1 - falloff` of `vision` at the edge of the field of view.
+    pub fn sees(&self, offset: &Vec2, vision: f32, falloff: f32) -> bool {
+        if self.fov >= TAU && falloff == 0. {
+            return offset.distance(&Vec2::new(0., 0.)) <= vision;
+        }
+
+        let half_fov = self.fov / 2.;
+        let angle = (offset.direction_radians() - self.direction + PI)
+            .rem_euclid(TAU)
+            .sub(PI)
+            .abs();
+        angle <= half_fov
+            && offset.distance(&Vec2::new(0., 0.)) <= vision * (1. - falloff * angle / half_fov)
+    }
+
+    /// Turn towards `direction`, as far as we can in `time_step`
+    pub fn turn_towards(&mut self, time_step: f32, direction: &Vec2) {
+        // Figure out if we should turn left or Right
+        let mut delta = (direction.direction_radians() - self.direction).rem_euclid(2. * PI);
+        if delta > PI {
+            delta = PI - delta;
+        }
+
+        // Clip to max turning speed
+        delta = delta.signum() * delta.abs().min(self.turning_speed * time_step);
+
+        self.direction += delta;
+    }
 }
 
 pub struct BoiTemplate<D: Distribution<f32>> {
+    pub species: SpeciesId,
     pub speed: D,
     pub vision: D,
+    pub fov: Option<D>, // All round if not given
     pub turning_speed: D,
+    pub weights: Weights<Sampled<D>>,
+    pub energy: f32,
 }
 
 impl<D: Distribution<f32>> EntityTemplate for BoiTemplate<D> {
     type Entity = Boi;
 
-    fn spawn<R: Rng>(&self, rng: &mut R, position: &Vec2, facing: f32) -> Self::Entity {
-        let choices = [(1., Species::Predator), (5., Species::Prey)];
-        let choice = choices.choose_weighted(rng, |item| item.0).unwrap().1;
-
+    fn spawn<R: Rng>(
+        &self,
+        rng: &mut R,
+        id: EntityId,
+        position: &Vec2,
+        facing: f32,
+    ) -> Self::Entity {
         Boi {
+            id,
             position: *position,
             direction: facing,
             speed: self.speed.sample(rng),
             vision: self.vision.sample(rng),
+            fov: self.fov.as_ref().map_or(TAU, |fov| fov.sample(rng)),
             turning_speed: self.turning_speed.sample(rng),
-            species: choice,
+            weights: self.weights.map(|weight| weight.sample(rng)),
+            energy: self.energy,
+            age: 0.,
+            species: self.species,
         }
     }
 }
 
-impl Strategy for Boi {
-    fn decide(&self, game_state: &crate::game::MainState) -> Vec2 {
-        // See who's around
-        let nearbois = game_state
-            // Query the tree since it's quicker
-            .boi_tree
-            .within(self.position.x, self.position.y, self.vision)
-            .into_iter()
-            // Get the bois based on the spatial query
-            .map(|i| {
-                game_state
-                    .bois
-                    .get(i as usize)
-                    .expect("Got invalid boi index!")
-            })
-            // Skip ourselves. todo: This is comparing that the entities are the same in memory,
-            // this might bite me in the ass later. Probably better to do some unique entity IDs on
-            // spawn instead.
-            .filter(|boi| !std::ptr::eq(*boi, self))
-            // Limit to 100 nearbois if we have way too many
-            //.take(10)
-            // Within some distance
-            .collect::<Vec<_>>();
-
-        // Split bois into friends & foes
-        let (friends, enemies) =
-            nearbois
-                .into_iter()
-                .fold((vec![], vec![]), |(mut friends, mut enemies), boi| {
-                    if boi.species == self.species {
-                        friends.push(boi);
-                    } else {
-                        enemies.push(boi);
-                    }
-
-                    (friends, enemies)
-                });
-
-        // Calculate the ideal vector for each thing. Each of them should be a unit vector
-        // reprenting a direction to go in
-
-        // Cache distances - todo: use result of query instead if crate maintainer implements it!
-        let friend_distances = friends
-            .iter()
-            .map(|boi| self.position.distance(&boi.position))
-            .collect::<Vec<_>>();
-        let enemy_distances = enemies
-            .iter()
-            .map(|boi| self.position.distance(&boi.position))
-            .collect::<Vec<_>>();
-
-        // Separation - Steer away from nearby bois - weight = 1 / distance
-        let separation = friends
-            .iter()
-            .map(|boi| self.position.sub(&boi.position).normalise())
-            .zip(&friend_distances)
-            .map(|(boi, distance)| boi.div(*distance))
-            .reduce(|a, b| a.add(&b))
-            .map(|v| v.normalise());
-
-        // Alignment - Align direction with average of nearby bois - weight = constant
-        let alignment = friends
-            .iter()
-            .map(|boi| boi.direction_vector())
-            .reduce(|a, b| a.add(&b))
-            .map(|v| v.normalise());
-
-        // Cohesion - Steer towards the centre of gravity of nearbois
-        let cohesion = friends
-            .iter()
-            .map(|boi| boi.position.div(friends.len() as f32))
-            .reduce(|a, b| a.add(&b))
-            .map(|centre_of_gravity| centre_of_gravity.sub(&self.position).normalise());
+#[cfg(test)]
+impl Boi {
+    /// A plain boi for tests, which sees all round & has the default weights
+    pub fn test_at(id: EntityId, species: SpeciesId, position: Vec2, direction: f32) -> Self {
+        Self {
+            id,
+            species,
+            position,
+            direction,
+            speed: 1.,
+            vision: 10.,
+            fov: TAU,
+            turning_speed: 1.,
+            weights: Weights::from_rules(&RuleWeights::default()),
+            energy: 1.,
+            age: 0.,
+        }
+    }
+}
 
-        // Attack - Steer towards the nearest prey boi
-        let attack = enemy_distances
-            .iter()
-            .zip(&enemies)
-            .filter(|(_, boi)| boi.species == Species::Prey)
-            .min_by(|(distance1, _), (distance2, _)| distance1.total_cmp(distance2))
-            .map(|(_, boi)| boi.position.sub(&self.position).normalise());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
 
-        // Defend - Steer away from the nearest predator boi
-        let defend = enemy_distances
-            .iter()
-            .zip(&enemies)
-            .filter(|(_, boi)| boi.species == Species::Predator)
-            .min_by(|(distance1, _), (distance2, _)| distance1.total_cmp(distance2))
-            .map(|(_, boi)| self.position.sub(&boi.position).normalise());
-
-        // Don't escape the arena - Steer towards centre of arena if we're too far away.
-        // Weight = nothing until we're close to the edge, then ramps up exponentially
-        let distance_to_centre = game_state.arena_centre.distance(&self.position);
-        let escape = if distance_to_centre > game_state.arena_radius {
-            Some(game_state.arena_centre.sub(&self.position).normalise())
-        } else {
-            None
+    #[test]
+    fn field_of_view() {
+        // Facing down the y axis, seeing 90 degrees either side
+        let boi = Boi {
+            fov: PI,
+            ..Boi::test_at(0, 0, Vec2::new(0., 0.), PI / 2.)
         };
+        assert!(boi.sees(&Vec2::new(0., 5.), 10., 0.));
+        assert!(boi.sees(&Vec2::new(5., 0.1), 10., 0.));
+        assert!(!boi.sees(&Vec2::new(0., -5.), 10., 0.));
+        assert!(!boi.sees(&Vec2::new(0., 11.), 10., 0.));
 
-        // Combine all the signals together
-        [
-            // Apply weighting for different factors, each of which may be null if there are no
-            // nearbois
-            separation.map(|x| x.mul(1.)),
-            alignment.map(|x| x.mul(1.)),
-            cohesion.map(|x| x.mul(1.)),
-            escape.map(|x| {
-                x.mul(
-                    distance_to_centre
-                        .sub(game_state.arena_radius)
-                        .max(0.)
-                        .powf(1.1),
-                )
-            }),
-            attack.map(|x| {
-                x.mul(if self.species == Species::Predator {
-                    5. // Predators always chase pray as top priority
-                } else {
-                    0.
-                })
-            }),
-            defend.map(|x| {
-                x.mul(if self.species == Species::Prey {
-                    5. // Prey always run away from predators as top priority
-                } else {
-                    0.
-                })
-            }),
-        ]
-        .into_iter()
-        // Discard null signals
-        .flatten()
-        // Weighted avg
-        .reduce(|a, b| a.add(&b))
-        // If there's no signal, keep on truckin'
-        .unwrap_or_else(|| self.direction_vector())
+        // Peripheral vision only reaches half as far at the edge
+        assert!(boi.sees(&Vec2::new(0., 9.), 10., 0.5));
+        assert!(!boi.sees(&Vec2::new(9., 0.01), 10., 0.5));
+        assert!(boi.sees(&Vec2::new(4., 0.01), 10., 0.5));
     }
 
-    fn action(&mut self, time_step: f32, direction: &Vec2) {
-        // Figure out if we should turn left or Right
-        let mut delta = (direction.direction_radians() - self.direction).rem_euclid(2. * PI);
-        if delta > PI {
-            delta = PI - delta;
-        }
-
-        // Clip to max turning speed
-        delta = delta.signum() * delta.abs().min(self.turning_speed * time_step);
+    #[test]
+    fn predators_sneak_up_from_behind() {
+        let mut config = Config::default();
+        config.world.num_bois = 0;
+        let mut world = World::new(&config, 1);
 
-        self.direction += delta;
+        // Prey heading along the x axis, with a predator right behind it & then right in front
+        let prey = Boi {
+            fov: PI,
+            ..Boi::test_at(0, 1, Vec2::new(0., 0.), 0.)
+        };
+        let mut decide = |predator: Vec2| {
+            let predator = Boi::test_at(1, 0, predator, 0.);
+            world.bois = vec![prey.clone(), predator.clone()];
+            world.boi_index = config
+                .world
+                .spatial_index
+                .build(&[prey.position, predator.position]);
+            world.decide(&prey)
+        };
+        assert_eq!(decide(Vec2::new(-3., 0.1)), prey.direction_vector());
+        assert!(decide(Vec2::new(3., 0.1)).x < 0.);
     }
 }
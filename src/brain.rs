@@ -0,0 +1,277 @@
+//! Small feed-forward networks that species can steer by instead of the hand-written rules. Their
+//! weights are evolved, see [crate::evolve].
+
+use std::{f32::consts::TAU, fmt, fs, io, path::Path};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{perception::Perception, strategy::Strategy, vec::Vec2};
+
+/// How many slices the space around a boi is cut into when sensing how crowded it is
+pub const SECTORS: usize = 8;
+/// Sector densities, then the nearest enemy's bearing (sin & cos), closeness & whether it's a
+/// threat or a meal, then how close the arena edge is & the bearing to the centre (sin & cos)
+pub const INPUTS: usize = SECTORS + 7;
+/// Where to head, as x (straight ahead) & y (off to the side) relative to where we're facing
+pub const OUTPUTS: usize = 2;
+
+/// The shape & weights of a network, which is what gets evolved & saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Genome {
+    pub hidden: Vec<usize>, // Neurons in each hidden layer
+    pub weights: Vec<f32>,  // Each layer's weights then biases, one neuron at a time
+    #[serde(default)]
+    pub fitness: f32, // How well it did the last time it was scored
+}
+
+/// Where a species' brain comes from, as it's written in the scenario file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BrainConfig {
+    /// The fittest genome in a file saved by `--evolve`. Turned into [BrainConfig::Genome] when
+    /// the scenario is loaded, with the path relative to the scenario.
+    File {
+        path: String,
+    },
+    Genome(Genome),
+}
+
+impl BrainConfig {
+    /// Read in a file brain. A genome stays as it is.
+    pub fn load_file(&mut self, dir: &Path) -> Result<(), GenomeError> {
+        let BrainConfig::File { path } = self else {
+            return Ok(());
+        };
+
+        let genome = Genome::load(dir.join(&*path))?
+            .into_iter()
+            .next()
+            .ok_or(GenomeError::Empty)?;
+        *self = BrainConfig::Genome(genome);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum GenomeError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Empty,
+}
+
+impl fmt::Display for GenomeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenomeError::Io(e) => write!(f, "couldn't read genomes: {}", e),
+            GenomeError::Json(e) => write!(f, "couldn't parse genomes: {}", e),
+            GenomeError::Empty => write!(f, "no genomes in the file"),
+        }
+    }
+}
+
+impl std::error::Error for GenomeError {}
+
+impl Genome {
+    /// Number of weights a network with these hidden layers needs
+    pub fn size(hidden: &[usize]) -> usize {
+        layers(hidden)
+            .windows(2)
+            .map(|layer| (layer[0] + 1) * layer[1])
+            .sum()
+    }
+
+    /// Weights picked uniformly between -1 & 1
+    pub fn random<R: Rng>(hidden: &[usize], rng: &mut R) -> Self {
+        Self {
+            hidden: hidden.to_vec(),
+            weights: (0..Self::size(hidden))
+                .map(|_| rng.gen_range(-1. ..=1.))
+                .collect(),
+            fitness: 0.,
+        }
+    }
+
+    /// Read a list of genomes saved by [Genome::save], fittest first
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>, GenomeError> {
+        let text = fs::read_to_string(path).map_err(GenomeError::Io)?;
+        serde_json::from_str(&text).map_err(GenomeError::Json)
+    }
+
+    pub fn save(path: impl AsRef<Path>, genomes: &[Self]) -> Result<(), GenomeError> {
+        let text = serde_json::to_string_pretty(genomes).map_err(GenomeError::Json)?;
+        fs::write(path, text).map_err(GenomeError::Io)
+    }
+}
+
+/// Neurons in every layer, from the inputs to the outputs
+fn layers(hidden: &[usize]) -> Vec<usize> {
+    [&[INPUTS][..], hidden, &[OUTPUTS]].concat()
+}
+
+/// A network built from a genome, ready to steer by
+pub struct Brain {
+    layers: Vec<usize>,
+    weights: Vec<f32>,
+}
+
+impl Brain {
+    /// Assumes the genome has already been validated, so it has the right number of weights
+    pub fn new(genome: &Genome) -> Self {
+        Self {
+            layers: layers(&genome.hidden),
+            weights: genome.weights.clone(),
+        }
+    }
+
+    /// Run the inputs through the network. Every neuron squashes its output with tanh.
+    pub fn think(&self, inputs: &[f32]) -> Vec<f32> {
+        let mut values = inputs.to_vec();
+        let mut weights = self.weights.as_slice();
+        for size in &self.layers[1..] {
+            values = (0..*size)
+                .map(|_| {
+                    // Each neuron's weights for every input, then its bias
+                    let (neuron, rest) = weights.split_at(values.len() + 1);
+                    weights = rest;
+                    let sum = values
+                        .iter()
+                        .zip(neuron)
+                        .map(|(value, weight)| value * weight)
+                        .sum::<f32>();
+                    (sum + neuron[values.len()]).tanh()
+                })
+                .collect();
+        }
+        values
+    }
+}
+
+/// Turn what a boi can perceive into inputs for a network. Everything is relative to the way it's
+/// facing & scaled to roughly -1 to 1.
+pub fn senses(perception: &Perception) -> [f32; INPUTS] {
+    let mut inputs = [0.; INPUTS];
+    let bearing = |offset: &Vec2| offset.direction_radians() - perception.heading;
+    let closeness = |distance: f32| (1. - distance / perception.vision).clamp(0., 1.);
+
+    // How crowded it is with friends in each direction, counting closer ones for more
+    for friend in perception.friends() {
+        let sector = (bearing(&friend.offset).rem_euclid(TAU) / TAU * SECTORS as f32) as usize;
+        inputs[sector.min(SECTORS - 1)] += closeness(friend.distance);
+    }
+    inputs[..SECTORS]
+        .iter_mut()
+        .for_each(|density| *density = density.tanh());
+
+    // Nearest boi of any other species
+    let enemy = perception
+        .neighbours
+        .iter()
+        .filter(|neighbour| neighbour.species != perception.species)
+        .min_by(|a, b| a.distance.total_cmp(&b.distance));
+    if let Some(enemy) = enemy {
+        let (sin, cos) = bearing(&enemy.offset).sin_cos();
+        inputs[SECTORS] = sin;
+        inputs[SECTORS + 1] = cos;
+        inputs[SECTORS + 2] = closeness(enemy.distance);
+        inputs[SECTORS + 3] = enemy.predator as u8 as f32 - enemy.prey as u8 as f32;
+    }
+
+    // Edge of the arena, only felt once it's within sight
+    let boundary = &perception.boundary;
+    inputs[SECTORS + 4] = (1. - boundary.edge_distance() / perception.vision).clamp(0., 2.);
+    let (sin, cos) = bearing(&boundary.centre).sin_cos();
+    inputs[SECTORS + 5] = sin;
+    inputs[SECTORS + 6] = cos;
+
+    inputs
+}
+
+impl Strategy for Brain {
+    fn decide(&self, perception: &Perception) -> Vec2 {
+        let outputs = self.think(&senses(perception));
+        let (ahead, side) = (outputs[0], outputs[1]);
+        if ahead == 0. && side == 0. {
+            return perception.direction_vector();
+        }
+
+        // Back from our point of view to the world's
+        let (sin, cos) = perception.heading.sin_cos();
+        Vec2::new(ahead * cos - side * sin, ahead * sin + side * cos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        boi::Weights,
+        config::RuleWeights,
+        perception::{Boundary, Environment, Neighbour},
+    };
+
+    #[test]
+    fn thinking() {
+        // No hidden layers, one weight per input per output, then the biases
+        let mut genome = Genome {
+            hidden: vec![],
+            weights: vec![0.; (INPUTS + 1) * OUTPUTS],
+            fitness: 0.,
+        };
+        assert_eq!(genome.weights.len(), Genome::size(&[]));
+        assert_eq!(Genome::size(&[4]), (INPUTS + 1) * 4 + 5 * OUTPUTS);
+
+        // First output is all bias, second passes the first input straight through
+        genome.weights[INPUTS] = 10.;
+        genome.weights[INPUTS + 1] = 1.;
+        let mut inputs = [0.; INPUTS];
+        inputs[0] = 0.5;
+        let outputs = Brain::new(&genome).think(&inputs);
+        assert!((outputs[0] - 10f32.tanh()).abs() < 1e-6);
+        assert!((outputs[1] - 0.5f32.tanh()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sensing() {
+        let neighbour = |species, x, y| Neighbour {
+            offset: Vec2::new(x, y),
+            position: Vec2::new(x, y + 95.),
+            distance: Vec2::new(x, y).distance(&Vec2::new(0., 0.)),
+            heading: 0.,
+            species,
+            prey: false,
+            predator: species == 0,
+        };
+        // Facing down the y axis, near the edge, with a friend ahead & a predator off to the side
+        let perception = Perception {
+            species: 1,
+            position: Vec2::new(0., 95.),
+            heading: TAU / 4.,
+            speed: 1.,
+            vision: 10.,
+            weights: Weights::from_rules(&RuleWeights::default()),
+            neighbours: vec![neighbour(1, -0.1, 5.), neighbour(0, -5., 0.)],
+            boundary: Boundary {
+                centre: Vec2::new(0., -95.),
+                distance: 95.,
+                radius: 100.,
+                obstacle: None,
+            },
+            environment: Environment {
+                flow: Vec2::new(0., 0.),
+                food: None,
+            },
+        };
+
+        let inputs = senses(&perception);
+        assert!((inputs[0] - 0.5f32.tanh()).abs() < 1e-2);
+        assert!(inputs[1..SECTORS].iter().all(|&density| density == 0.));
+        assert!((inputs[SECTORS] - 1.).abs() < 1e-5);
+        assert!(inputs[SECTORS + 1].abs() < 1e-5);
+        assert!((inputs[SECTORS + 2] - 0.5).abs() < 1e-5);
+        assert_eq!(inputs[SECTORS + 3], 1.);
+        assert!((inputs[SECTORS + 4] - 0.5).abs() < 1e-5);
+        assert!((inputs[SECTORS + 6] + 1.).abs() < 1e-5);
+    }
+}
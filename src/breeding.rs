@@ -0,0 +1,284 @@
+use std::f32::consts::TAU;
+
+use rand_distr::{Distribution, Normal};
+
+use crate::{
+    boi::{Boi, Weights},
+    config::{Range, SpeciesConfig},
+    entity::EntityId,
+    species::SpeciesId,
+    world::{SimRng, World},
+};
+
+/// How far behind its parent an offspring starts. Starting right on top of the parent would leave
+/// them with no direction to separate in.
+const BIRTH_OFFSET: f32 = 0.5;
+
+/// Record of a boi being born
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Birth {
+    pub child: EntityId,
+    pub species: SpeciesId,
+    pub parents: (EntityId, Option<EntityId>), // Second parent is None if it bred on its own
+}
+
+/// Whether a boi is up for breeding
+fn ready(species: &[SpeciesConfig], boi: &Boi) -> bool {
+    match &species[boi.species].breeding {
+        Some(breeding) => boi.energy >= breeding.min_energy && boi.age >= breeding.min_age,
+        None => false,
+    }
+}
+
+/// Average of the parents' trait, plus a bit of noise, kept within the species' range
+fn inherit(
+    rng: &mut SimRng,
+    parents: &[&Boi],
+    value: fn(&Boi) -> f32,
+    sd: f32,
+    range: &Range,
+) -> f32 {
+    let mean = parents.iter().map(|boi| value(boi)).sum::<f32>() / parents.len() as f32;
+    mutate(rng, mean, sd, range)
+}
+
+fn mutate(rng: &mut SimRng, value: f32, sd: f32, range: &Range) -> f32 {
+    let noise = Normal::new(0., sd)
+        .expect("Mutation should have been validated")
+        .sample(rng);
+    // The top of the range is exclusive, same as when bois are first spawned
+    (value + noise).clamp(range.min, range.max.next_down())
+}
+
+impl World {
+    /// Let everyone who's ready have offspring. Each boi breeds at most once per call, and the
+    /// offspring are added to the end of `bois`.
+    ///
+    /// Expects the spatial index to be up to date, and leaves it out of date if anyone was born.
+    pub(crate) fn breed(&mut self) {
+        let species = &self.config.species;
+        let mut bred = vec![false; self.bois.len()];
+        let mut children = vec![];
+        let mut costs = vec![]; // Energy each parent owes, by index
+        self.births.clear();
+
+        for i in 0..self.bois.len() {
+            let parent = &self.bois[i];
+            if bred[i] || !ready(species, parent) {
+                continue;
+            }
+            let config = &species[parent.species];
+            let breeding = config.breeding.as_ref().unwrap();
+
+            // Find the closest willing mate, if we need one
+            let mate = if breeding.mate_radius > 0. {
+                let mate = self
+                    .boi_index
+                    .within(&parent.position, breeding.mate_radius)
+                    .into_iter()
+                    .filter(|n| {
+                        let other = &self.bois[n.index];
+                        n.index != i
+                            && !bred[n.index]
+                            && other.species == parent.species
+                            && ready(species, other)
+                    })
+                    .min_by(|a, b| a.distance.total_cmp(&b.distance));
+                match mate {
+                    Some(mate) => Some(mate.index),
+                    // Nobody around
+                    None => continue,
+                }
+            } else {
+                None
+            };
+
+            let parents = [Some(i), mate]
+                .into_iter()
+                .flatten()
+                .map(|j| &self.bois[j])
+                .collect::<Vec<_>>();
+            let id = self.nest.new_id();
+            let rng = &mut self.nest.rng;
+            let mutation = &breeding.mutation;
+            let child = Boi {
+                id,
+                species: parent.species,
+                position: self
+                    .obstacles
+                    .push_out(
+                        &parent
+                            .position
+                            .sub(&parent.direction_vector().mul(BIRTH_OFFSET)),
+                    )
+                    .unwrap_or(parent.position),
+                direction: parent.direction,
+                speed: inherit(rng, &parents, |b| b.speed, mutation.speed, &config.speed),
+                vision: inherit(rng, &parents, |b| b.vision, mutation.vision, &config.vision),
+                fov: match &config.fov {
+                    Some(fov) => inherit(
+                        rng,
+                        &parents,
+                        |b| b.fov,
+                        mutation.fov.to_radians(),
+                        &fov.to_radians(),
+                    ),
+                    None => TAU,
+                },
+                turning_speed: inherit(
+                    rng,
+                    &parents,
+                    |b| b.turning_speed,
+                    mutation.turning_speed,
+                    &config.turning_speed,
+                ),
+                weights: {
+                    let mean = Weights::mean(parents.iter().map(|boi| boi.weights)).unwrap();
+                    // Anything the species doesn't vary stays as it is
+                    config.weights.zip(mean).map(|(range, mean)| match range {
+                        Some(range) => mutate(rng, *mean, mutation.weights, range),
+                        None => *mean,
+                    })
+                },
+                energy: breeding.cost.min(config.energy.max),
+                age: 0.,
+            };
+
+            self.births.push(Birth {
+                child: child.id,
+                species: child.species,
+                parents: (parent.id, mate.map(|j| self.bois[j].id)),
+            });
+            children.push(child);
+
+            // Parents split the cost between them
+            let share = breeding.cost / parents.len() as f32;
+            for j in [Some(i), mate].into_iter().flatten() {
+                bred[j] = true;
+                costs.push((j, share));
+            }
+        }
+
+        for (j, cost) in costs {
+            self.bois[j].energy -= cost;
+        }
+        self.bois.extend(children);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BreedingConfig, Config, Range};
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn breeding_config(mate_radius: f32) -> Config {
+        let mut config = Config::default();
+        config.world.arena_radius = 30.;
+        config.world.num_bois = 100;
+        // Just prey, who regain energy quickly & breed once they've got enough
+        config.species.remove(0);
+        config.species[0].energy.initial = 50.;
+        config.species[0].energy.regen = 30.;
+        config.species[0].breeding = Some(BreedingConfig {
+            min_energy: 90.,
+            min_age: 1.,
+            cost: 60.,
+            mate_radius,
+            ..Default::default()
+        });
+        config
+    }
+
+    #[test]
+    fn offspring_inherit_within_range() {
+        let config = breeding_config(0.);
+        let mut world = World::new(&config, 3);
+        let mut traits = world
+            .bois
+            .iter()
+            .map(|boi| (boi.id, boi.speed))
+            .collect::<HashMap<_, _>>();
+
+        let mut born = 0;
+        for _ in 0..90 {
+            world.step(1. / 30.);
+            for birth in &world.births {
+                assert_eq!(birth.parents.1, None);
+                let child = world.bois.iter().find(|b| b.id == birth.child).unwrap();
+                let parent_speed = traits[&birth.parents.0];
+                assert!((child.speed - parent_speed).abs() < 1.);
+                traits.insert(child.id, child.speed);
+            }
+            born += world.births.len();
+        }
+
+        assert!(born >= 100, "only {} born", born);
+        assert_eq!(world.bois.len(), 100 + born);
+        let speed = &config.species[0].speed;
+        assert!(world
+            .bois
+            .iter()
+            .all(|boi| boi.speed >= speed.min && boi.speed < speed.max));
+        // IDs are never handed out twice
+        assert_eq!(traits.len(), world.bois.len());
+    }
+
+    #[test]
+    fn mutations_stay_below_the_top_of_the_range() {
+        let mut rng = SimRng::seed_from_u64(1);
+        let range = Range::new(1., 2.);
+        assert!((0..100).all(|_| {
+            let value = mutate(&mut rng, 2., 10., &range);
+            (1. ..2.).contains(&value)
+        }));
+    }
+
+    #[test]
+    fn personalities_are_passed_on() {
+        let mut config = breeding_config(0.);
+        config.species[0].weights.cohesion = Some(Range::new(0., 4.));
+        let mut world = World::new(&config, 5);
+        let mut cohesion = world
+            .bois
+            .iter()
+            .map(|boi| (boi.id, boi.weights.cohesion))
+            .collect::<HashMap<_, _>>();
+        assert!(cohesion.values().any(|&c| c != cohesion[&world.bois[0].id]));
+
+        for _ in 0..90 {
+            world.step(1. / 30.);
+            for birth in &world.births {
+                let child = world.bois.iter().find(|b| b.id == birth.child).unwrap();
+                assert!((child.weights.cohesion - cohesion[&birth.parents.0]).abs() < 1.);
+                assert!((0. ..=4.).contains(&child.weights.cohesion));
+                // Nothing else varies
+                assert_eq!(child.weights.separation, config.rules.separation);
+                assert_eq!(child.weights.attack, config.rules.attack);
+                cohesion.insert(child.id, child.weights.cohesion);
+            }
+        }
+        assert!(world.bois.len() > 100);
+    }
+
+    #[test]
+    fn needs_a_mate() {
+        // Too far apart to ever find each other
+        let config = breeding_config(0.01);
+        let mut world = World::new(&config, 3);
+        for _ in 0..90 {
+            world.step(1. / 30.);
+        }
+        assert_eq!(world.bois.len(), 100);
+
+        // Close enough for everyone to find someone
+        let config = breeding_config(100.);
+        let mut world = World::new(&config, 3);
+        for _ in 0..90 {
+            world.step(1. / 30.);
+            assert!(world.births.iter().all(|birth| birth.parents.1.is_some()));
+        }
+        assert!(world.bois.len() > 100);
+    }
+}
@@ -0,0 +1,852 @@
+use std::{fmt, fs, path::Path};
+
+use rand::distributions::Uniform;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    biome::BiomeConfig,
+    boi::Weights,
+    brain::{BrainConfig, Genome},
+    evolve::EvolutionConfig,
+    flow::{FieldConfig, FlowConfig},
+    food::FoodConfig,
+    shape::{signed_area, Shape, ShapeConfig},
+    sight::OcclusionConfig,
+    spatial::SpatialIndexConfig,
+    steering::RuleKind,
+};
+
+/// Everything needed to set up a scenario. Any fields missing from the scenario file fall back to
+/// the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub world: WorldConfig,
+    pub render: RenderConfig,
+    pub species: Vec<SpeciesConfig>,
+    pub rules: RuleWeights,
+    pub obstacles: Vec<ShapeConfig>,
+    pub biomes: Vec<BiomeConfig>,
+    pub flow: Vec<FlowConfig>,
+    pub food: Option<FoodConfig>, // No food layer if not given
+    pub occlusion: OcclusionConfig,
+    pub evolution: Option<EvolutionConfig>, // Only needed for --evolve
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            world: WorldConfig::default(),
+            render: RenderConfig::default(),
+            species: vec![
+                SpeciesConfig {
+                    name: "predator".into(),
+                    colour: [255, 0, 0],
+                    spawn_weight: 1.,
+                    eats: vec!["prey".into()],
+                    ..Default::default()
+                },
+                SpeciesConfig {
+                    name: "prey".into(),
+                    colour: [0, 255, 0],
+                    spawn_weight: 5.,
+                    ..Default::default()
+                },
+            ],
+            rules: RuleWeights::default(),
+            obstacles: vec![],
+            biomes: vec![],
+            flow: vec![],
+            food: None,
+            occlusion: OcclusionConfig::default(),
+            evolution: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub seed: Option<u64>, // Picked at random if not given
+    pub arena_radius: f32, // World units
+    pub num_bois: usize,
+    pub kill_radius: f32, // How close a predator needs to get to gobble up prey
+    pub tick_rate: u32,   // Simulation ticks per second of simulated time
+    pub spatial_index: SpatialIndexConfig,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            arena_radius: 100.,
+            num_bois: 1000,
+            kill_radius: 1.,
+            tick_rate: 30,
+            spatial_index: SpatialIndexConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    pub screen_scale: f32,  // How much bigger is the rendering than the world
+    pub padding: f32,       // Pixels of padding on each side of the arena
+    pub time_scale: f32, // Seconds of simulated time per real second. Can be changed while running.
+    pub show_metrics: bool, // Show flock order parameters in the corner. Can be toggled while running.
+    pub show_flow: bool, // Draw arrows showing any wind or current. Can be toggled while running.
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            screen_scale: 3.,
+            padding: 100.,
+            time_scale: 1.,
+            show_metrics: false,
+            show_flow: false,
+        }
+    }
+}
+
+/// Inclusive lower & exclusive upper bound that a trait is uniformly sampled from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Range {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Range {
+    pub fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    pub fn uniform(&self) -> Uniform<f32> {
+        Uniform::new(self.min, self.max)
+    }
+
+    /// Same range, from degrees to radians
+    pub fn to_radians(&self) -> Self {
+        Self::new(self.min.to_radians(), self.max.to_radians())
+    }
+}
+
+/// A single species, along with the ranges its traits are sampled from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesConfig {
+    pub name: String,
+    pub colour: [u8; 3],   // RGB
+    pub spawn_weight: f32, // Relative chance of this species being spawned
+    pub eats: Vec<String>, // Names of the species this one gobbles up
+
+    pub speed: Range,
+    pub vision: Range,
+    pub turning_speed: Range,
+    pub fov: Option<Range>, // Field of view in degrees, centred on where the boi's facing. All round if not given.
+    // How much shorter peripheral vision is, from 0 (sees as far at the edge of the field of view as
+    // straight ahead) to 1 (can barely see anything at the edge)
+    pub peripheral_falloff: f32,
+    // Ranges each boi's rule weights are sampled from. Any that aren't given are the same for
+    // everyone, straight from [rules].
+    pub weights: Weights<Option<Range>>,
+    // Rules this species steers by, added up in this order. Everything built in if not given.
+    pub steering: Vec<RuleKind>,
+    pub brain: Option<BrainConfig>, // Steer by an evolved network instead of the rules
+
+    pub energy: EnergyConfig,
+    pub breeding: Option<BreedingConfig>, // Never breeds if not given
+
+    // How much of the flow a boi feels & steers against, from 0 (drifts along with it) to 1
+    // (holds its course as best it can)
+    pub flow_compensation: f32,
+}
+
+impl Default for SpeciesConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            colour: [255, 255, 255],
+            spawn_weight: 1.,
+            eats: vec![],
+            speed: Range::new(2., 3.),
+            vision: Range::new(2., 10.),
+            turning_speed: Range::new(0.1, 0.5),
+            fov: None,
+            peripheral_falloff: 0.,
+            weights: Weights::default(),
+            steering: RuleKind::ALL.to_vec(),
+            brain: None,
+            energy: EnergyConfig::default(),
+            breeding: None,
+            flow_compensation: 0.,
+        }
+    }
+}
+
+/// How a species gains & spends energy. Anyone who hits zero starves. The defaults never change
+/// anyone's energy, so nobody starves unless the scenario says so.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnergyConfig {
+    pub initial: f32,   // Energy each boi spawns with
+    pub max: f32,       // Can't store any more than this
+    pub move_cost: f32, // Energy spent per world unit travelled
+    pub turn_cost: f32, // Energy spent per radian turned
+    pub regen: f32,     // Energy gained per second just by being alive, eg. grazing
+    pub nutrition: f32, // Energy gained by whoever eats one of these
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            initial: 100.,
+            max: 100.,
+            move_cost: 0.,
+            turn_cost: 0.,
+            regen: 0.,
+            nutrition: 0.,
+        }
+    }
+}
+
+/// When a species breeds, and how much its offspring differ from their parents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreedingConfig {
+    pub min_energy: f32,  // Need at least this much energy to breed
+    pub min_age: f32,     // Seconds a boi has to have been alive before breeding
+    pub cost: f32,        // Energy handed over to the offspring, split between the parents
+    pub mate_radius: f32, // How close a willing mate needs to be. 0 breeds on its own.
+    pub mutation: Mutation,
+}
+
+impl Default for BreedingConfig {
+    fn default() -> Self {
+        Self {
+            min_energy: 80.,
+            min_age: 10.,
+            cost: 50.,
+            mate_radius: 0.,
+            mutation: Mutation::default(),
+        }
+    }
+}
+
+/// Standard deviation of the noise added to each trait an offspring inherits. Traits are always
+/// kept within the species' ranges.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mutation {
+    pub speed: f32,
+    pub vision: f32,
+    pub turning_speed: f32,
+    pub fov: f32,     // Degrees
+    pub weights: f32, // For every rule weight
+}
+
+impl Default for Mutation {
+    fn default() -> Self {
+        Self {
+            speed: 0.1,
+            vision: 0.5,
+            turning_speed: 0.02,
+            fov: 5.,
+            weights: 0.1,
+        }
+    }
+}
+
+/// How much each rule contributes when a boi decides where to go
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleWeights {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub attack: f32, // Steer towards the nearest thing we can eat
+    pub defend: f32, // Steer away from the nearest thing that can eat us
+    pub avoid: f32,  // Steer away from obstacles up ahead, ramping up the closer they get
+    pub forage: f32, // Grazers steer towards wherever there's more food
+    // Escape weight ramps up as (distance past the arena edge) ^ escape_exponent
+    pub escape_exponent: f32,
+}
+
+impl Default for RuleWeights {
+    fn default() -> Self {
+        Self {
+            separation: 1.,
+            alignment: 1.,
+            cohesion: 1.,
+            attack: 5.,
+            defend: 5.,
+            avoid: 10.,
+            forage: 1.,
+            escape_exponent: 1.1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Couldn't read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Couldn't parse config file: {}", e),
+            ConfigError::Invalid(msg) => write!(f, "Invalid config: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load a scenario from a TOML file, making sure it's sensible
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let mut config: Self = toml::from_str(&text).map_err(ConfigError::Parse)?;
+
+        // Images & brains are relative to the scenario
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for (i, flow) in config.flow.iter_mut().enumerate() {
+            // Checked now, as there's no telling once it's been turned into a grid
+            image_field(&format!("flow #{}", i + 1), &flow.field)?;
+            flow.field
+                .load_image(dir)
+                .map_err(|e| invalid(format!("couldn't load flow #{} image: {}", i + 1, e)))?;
+        }
+        for species in &mut config.species {
+            if let Some(brain) = &mut species.brain {
+                brain.load_file(dir).map_err(|e| {
+                    invalid(format!(
+                        "couldn't load species.{} brain: {}",
+                        species.name, e
+                    ))
+                })?;
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check for any values that would break the simulation
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        positive("world.arena_radius", self.world.arena_radius)?;
+        positive("world.kill_radius", self.world.kill_radius)?;
+        if self.world.tick_rate == 0 {
+            return Err(invalid("world.tick_rate must be greater than 0"));
+        }
+        if let SpatialIndexConfig::Grid { cell_size } = self.world.spatial_index {
+            positive("world.spatial_index.cell_size", cell_size)?;
+        }
+
+        positive("render.screen_scale", self.render.screen_scale)?;
+        non_negative("render.padding", self.render.padding)?;
+        positive("render.time_scale", self.render.time_scale)?;
+
+        if self.species.is_empty() {
+            return Err(invalid("at least one species must be defined"));
+        }
+        for (i, species) in self.species.iter().enumerate() {
+            if species.name.is_empty() {
+                return Err(invalid(format!("species #{} needs a name", i + 1)));
+            }
+            if self.species[..i].iter().any(|s| s.name == species.name) {
+                return Err(invalid(format!(
+                    "species \"{}\" is defined more than once",
+                    species.name
+                )));
+            }
+            for food in &species.eats {
+                if !self.species.iter().any(|s| &s.name == food) {
+                    return Err(invalid(format!(
+                        "species \"{}\" eats \"{}\", which isn't a species",
+                        species.name, food
+                    )));
+                }
+            }
+
+            let name = format!("species.{}", species.name);
+            non_negative(&format!("{}.spawn_weight", name), species.spawn_weight)?;
+            range(&format!("{}.speed", name), &species.speed)?;
+            range(&format!("{}.vision", name), &species.vision)?;
+            range(&format!("{}.turning_speed", name), &species.turning_speed)?;
+            non_negative(&format!("{}.speed.min", name), species.speed.min)?;
+            non_negative(&format!("{}.vision.min", name), species.vision.min)?;
+            non_negative(
+                &format!("{}.turning_speed.min", name),
+                species.turning_speed.min,
+            )?;
+            if let Some(fov) = &species.fov {
+                range(&format!("{}.fov", name), fov)?;
+                if fov.min <= 0. || fov.max > 360. {
+                    return Err(invalid(format!(
+                        "{}.fov must be within 0 to 360 degrees, got {} to {}",
+                        name, fov.min, fov.max
+                    )));
+                }
+            }
+            let weights = &species.weights;
+            for (rule, weight) in [
+                ("separation", &weights.separation),
+                ("alignment", &weights.alignment),
+                ("cohesion", &weights.cohesion),
+                ("attack", &weights.attack),
+                ("defend", &weights.defend),
+            ] {
+                if let Some(weight) = weight {
+                    let name = format!("{}.weights.{}", name, rule);
+                    range(&name, weight)?;
+                    non_negative(&format!("{}.min", name), weight.min)?;
+                }
+            }
+            for (i, rule) in species.steering.iter().enumerate() {
+                if species.steering[..i].contains(rule) {
+                    return Err(invalid(format!(
+                        "{}.steering lists \"{}\" more than once",
+                        name, rule
+                    )));
+                }
+            }
+            if let Some(brain) = &species.brain {
+                self::brain(&format!("{}.brain", name), brain)?;
+            }
+            if !(0. ..=1.).contains(&species.peripheral_falloff) {
+                return Err(invalid(format!(
+                    "{}.peripheral_falloff must be between 0 and 1, got {}",
+                    name, species.peripheral_falloff
+                )));
+            }
+
+            let energy = &species.energy;
+            positive(&format!("{}.energy.max", name), energy.max)?;
+            positive(&format!("{}.energy.initial", name), energy.initial)?;
+            if energy.initial > energy.max {
+                return Err(invalid(format!(
+                    "{}.energy.initial ({}) can't be more than {}.energy.max ({})",
+                    name, energy.initial, name, energy.max
+                )));
+            }
+            non_negative(&format!("{}.energy.move_cost", name), energy.move_cost)?;
+            non_negative(&format!("{}.energy.turn_cost", name), energy.turn_cost)?;
+            non_negative(&format!("{}.energy.regen", name), energy.regen)?;
+            non_negative(&format!("{}.energy.nutrition", name), energy.nutrition)?;
+            if !(0. ..=1.).contains(&species.flow_compensation) {
+                return Err(invalid(format!(
+                    "{}.flow_compensation must be between 0 and 1, got {}",
+                    name, species.flow_compensation
+                )));
+            }
+
+            if let Some(breeding) = &species.breeding {
+                let name = format!("{}.breeding", name);
+                non_negative(&format!("{}.min_energy", name), breeding.min_energy)?;
+                non_negative(&format!("{}.min_age", name), breeding.min_age)?;
+                positive(&format!("{}.cost", name), breeding.cost)?;
+                // A lone parent on exactly min_energy would be left with nothing & starve
+                if breeding.cost >= breeding.min_energy {
+                    return Err(invalid(format!(
+                        "{}.cost ({}) must be less than {}.min_energy ({}), or parents would starve",
+                        name, breeding.cost, name, breeding.min_energy
+                    )));
+                }
+                non_negative(&format!("{}.mate_radius", name), breeding.mate_radius)?;
+                let mutation = &breeding.mutation;
+                non_negative(&format!("{}.mutation.speed", name), mutation.speed)?;
+                non_negative(&format!("{}.mutation.vision", name), mutation.vision)?;
+                non_negative(
+                    &format!("{}.mutation.turning_speed", name),
+                    mutation.turning_speed,
+                )?;
+                non_negative(&format!("{}.mutation.fov", name), mutation.fov)?;
+                non_negative(&format!("{}.mutation.weights", name), mutation.weights)?;
+            }
+        }
+        if self.species.iter().map(|s| s.spawn_weight).sum::<f32>() <= 0. {
+            return Err(invalid(
+                "at least one species must have a positive spawn_weight",
+            ));
+        }
+
+        non_negative("rules.separation", self.rules.separation)?;
+        non_negative("rules.alignment", self.rules.alignment)?;
+        non_negative("rules.cohesion", self.rules.cohesion)?;
+        non_negative("rules.attack", self.rules.attack)?;
+        non_negative("rules.defend", self.rules.defend)?;
+        non_negative("rules.avoid", self.rules.avoid)?;
+        non_negative("rules.forage", self.rules.forage)?;
+        positive("rules.escape_exponent", self.rules.escape_exponent)?;
+
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            shape(&format!("obstacles #{}", i + 1), obstacle)?;
+        }
+
+        for (i, biome) in self.biomes.iter().enumerate() {
+            if biome.name.is_empty() {
+                return Err(invalid(format!("biome #{} needs a name", i + 1)));
+            }
+            let name = format!("biomes.{}", biome.name);
+            shape(&format!("{}.region", name), &biome.region)?;
+            for (species, modifiers) in &biome.modifiers {
+                if !self.species.iter().any(|s| &s.name == species) {
+                    return Err(invalid(format!(
+                        "biome \"{}\" has modifiers for \"{}\", which isn't a species",
+                        biome.name, species
+                    )));
+                }
+                let name = format!("{}.modifiers.{}", name, species);
+                non_negative(&format!("{}.speed", name), modifiers.speed)?;
+                non_negative(&format!("{}.vision", name), modifiers.vision)?;
+                non_negative(&format!("{}.turning_speed", name), modifiers.turning_speed)?;
+            }
+        }
+
+        non_negative("occlusion.body_radius", self.occlusion.body_radius)?;
+
+        if let Some(food) = &self.food {
+            positive("food.cell_size", food.cell_size)?;
+            positive("food.capacity", food.capacity)?;
+            non_negative("food.growth_rate", food.growth_rate)?;
+            non_negative("food.bite", food.bite)?;
+            non_negative("food.nutrition", food.nutrition)?;
+            for (name, value) in [("initial", food.initial), ("reserve", food.reserve)] {
+                if !(0. ..=1.).contains(&value) {
+                    return Err(invalid(format!(
+                        "food.{} must be between 0 and 1, got {}",
+                        name, value
+                    )));
+                }
+            }
+            for grazer in &food.grazers {
+                if !self.species.iter().any(|s| &s.name == grazer) {
+                    return Err(invalid(format!(
+                        "food is grazed by \"{}\", which isn't a species",
+                        grazer
+                    )));
+                }
+            }
+        }
+
+        for (i, flow) in self.flow.iter().enumerate() {
+            let name = format!("flow #{}", i + 1);
+            flow_field(&name, &flow.field)?;
+            if let Some(gust) = &flow.gust {
+                positive(&format!("{} gust.period", name), gust.period)?;
+                non_negative(&format!("{} gust.strength", name), gust.strength)?;
+                finite(&format!("{} gust.swing", name), gust.swing)?;
+            }
+        }
+
+        if let Some(evolution) = &self.evolution {
+            if !self.species.iter().any(|s| s.name == evolution.species) {
+                return Err(invalid(format!(
+                    "evolution.species \"{}\" isn't a species",
+                    evolution.species
+                )));
+            }
+            if evolution.hidden.contains(&0) {
+                return Err(invalid("evolution.hidden layers can't be empty"));
+            }
+            if evolution.population < 2 || evolution.episodes == 0 || evolution.ticks == 0 {
+                return Err(invalid(
+                    "evolution needs a population of at least 2 & at least one episode & tick",
+                ));
+            }
+            if evolution.elites == 0 || evolution.elites > evolution.population {
+                return Err(invalid(format!(
+                    "evolution.elites must be between 1 & the population ({}), got {}",
+                    evolution.population, evolution.elites
+                )));
+            }
+            non_negative("evolution.mutation", evolution.mutation)?;
+            if !(0. ..=1.).contains(&evolution.mutation_rate) {
+                return Err(invalid(format!(
+                    "evolution.mutation_rate must be between 0 and 1, got {}",
+                    evolution.mutation_rate
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn brain(name: &str, config: &BrainConfig) -> Result<(), ConfigError> {
+    match config {
+        BrainConfig::File { path } => Err(invalid(format!(
+            "{} \"{}\" can only be loaded from a scenario file",
+            name, path
+        ))),
+        BrainConfig::Genome(genome) => {
+            if genome.hidden.contains(&0) {
+                return Err(invalid(format!("{} hidden layers can't be empty", name)));
+            }
+            let size = Genome::size(&genome.hidden);
+            if genome.weights.len() != size {
+                return Err(invalid(format!(
+                    "{} needs {} weights for its hidden layers, got {}",
+                    name,
+                    size,
+                    genome.weights.len()
+                )));
+            }
+            genome
+                .weights
+                .iter()
+                .try_for_each(|weight| finite(&format!("{} weights", name), *weight))
+        }
+    }
+}
+
+fn shape(name: &str, config: &ShapeConfig) -> Result<(), ConfigError> {
+    match Shape::from(config) {
+        Shape::Circle { centre, radius } => {
+            if !(centre.x.is_finite() && centre.y.is_finite()) {
+                return Err(invalid(format!("{} has a bad centre", name)));
+            }
+            positive(&format!("{} radius", name), radius)
+        }
+        Shape::Polygon { points } => {
+            if points.len() < 3 {
+                return Err(invalid(format!(
+                    "{} needs at least 3 points, got {}",
+                    name,
+                    points.len()
+                )));
+            }
+            if points.iter().any(|p| !(p.x.is_finite() && p.y.is_finite())) {
+                return Err(invalid(format!("{} has a bad point", name)));
+            }
+            if signed_area(&points) == 0. {
+                return Err(invalid(format!("{} has no area", name)));
+            }
+            Ok(())
+        }
+    }
+}
+
+fn flow_field(name: &str, config: &FieldConfig) -> Result<(), ConfigError> {
+    match config {
+        FieldConfig::Uniform { velocity: [x, y] } => {
+            finite(&format!("{} velocity", name), *x)?;
+            finite(&format!("{} velocity", name), *y)
+        }
+        FieldConfig::Vortex {
+            centre: [x, y],
+            radius,
+            strength,
+        } => {
+            finite(&format!("{} centre", name), *x)?;
+            finite(&format!("{} centre", name), *y)?;
+            positive(&format!("{} radius", name), *radius)?;
+            finite(&format!("{} strength", name), *strength)
+        }
+        FieldConfig::Grid {
+            origin: [x, y],
+            spacing,
+            width,
+            velocities,
+        } => {
+            finite(&format!("{} origin", name), *x)?;
+            finite(&format!("{} origin", name), *y)?;
+            positive(&format!("{} spacing", name), *spacing)?;
+            if *width == 0 || velocities.is_empty() || velocities.len() % width != 0 {
+                return Err(invalid(format!(
+                    "{} needs whole rows of {} velocities, got {}",
+                    name,
+                    width,
+                    velocities.len()
+                )));
+            }
+            velocities
+                .iter()
+                .flatten()
+                .try_for_each(|v| finite(&format!("{} velocities", name), *v))
+        }
+        FieldConfig::Image { path, .. } => {
+            image_field(name, config)?;
+            Err(invalid(format!(
+                "{} image \"{}\" can only be used from a scenario file",
+                name, path
+            )))
+        }
+    }
+}
+
+/// The settings of an image field, before the image is loaded
+fn image_field(name: &str, config: &FieldConfig) -> Result<(), ConfigError> {
+    let FieldConfig::Image {
+        origin: [x, y],
+        spacing,
+        max_speed,
+        ..
+    } = config
+    else {
+        return Ok(());
+    };
+    finite(&format!("{} origin", name), *x)?;
+    finite(&format!("{} origin", name), *y)?;
+    positive(&format!("{} spacing", name), *spacing)?;
+    positive(&format!("{} max_speed", name), *max_speed)
+}
+
+fn invalid(msg: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid(msg.into())
+}
+
+fn positive(name: &str, value: f32) -> Result<(), ConfigError> {
+    // Written this way round so NaN gets rejected too
+    if value > 0. && value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(format!("{} must be positive, got {}", name, value)))
+    }
+}
+
+fn non_negative(name: &str, value: f32) -> Result<(), ConfigError> {
+    if value >= 0. && value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "{} must not be negative, got {}",
+            name, value
+        )))
+    }
+}
+
+fn finite(name: &str, value: f32) -> Result<(), ConfigError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(format!("{} must be a number, got {}", name, value)))
+    }
+}
+
+fn range(name: &str, range: &Range) -> Result<(), ConfigError> {
+    if range.min < range.max && range.min.is_finite() && range.max.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(format!(
+            "{} is an empty range, min ({}) must be less than max ({})",
+            name, range.min, range.max
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        let config = Config::from_toml("").unwrap();
+        assert_eq!(config.world.arena_radius, 100.);
+        assert_eq!(config.world.num_bois, 1000);
+        assert_eq!(config.rules.attack, 5.);
+    }
+
+    #[test]
+    fn rejects_negative_radius() {
+        let err = Config::from_toml("[world]\narena_radius = -5.0").unwrap_err();
+        assert!(err.to_string().contains("world.arena_radius"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_empty_range() {
+        let err =
+            Config::from_toml("[[species]]\nname = \"fish\"\nspeed = { min = 3.0, max = 2.0 }")
+                .unwrap_err();
+        assert!(err.to_string().contains("species.fish.speed"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_food() {
+        let err =
+            Config::from_toml("[[species]]\nname = \"shark\"\neats = [\"fish\"]").unwrap_err();
+        assert!(err.to_string().contains("\"fish\""), "{}", err);
+    }
+
+    #[test]
+    fn obstacles() {
+        let config = Config::from_toml(
+            "[[obstacles]]\nshape = \"circle\"\ncentre = [1.0, 2.0]\nradius = 3.0\n\n\
+             [[obstacles]]\nshape = \"polygon\"\npoints = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]",
+        )
+        .unwrap();
+        assert_eq!(config.obstacles.len(), 2);
+        // Survives a round trip, for snapshots
+        let text = toml::to_string(&config).unwrap();
+        assert_eq!(Config::from_toml(&text).unwrap().obstacles.len(), 2);
+
+        let err = Config::from_toml(
+            "[[obstacles]]\nshape = \"polygon\"\npoints = [[0.0, 0.0], [1.0, 0.0]]",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("at least 3 points"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_repeated_rules() {
+        let err = Config::from_toml(
+            "[[species]]\nname = \"fish\"\nsteering = [\"cohesion\", \"avoid\", \"cohesion\"]",
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("\"cohesion\" more than once"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_brain_of_wrong_size() {
+        let err = Config::from_toml(
+            "[[species]]\nname = \"fish\"\nbrain = { kind = \"genome\", hidden = [2], weights = [0.5] }",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("needs 38 weights"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_bad_flow_image() {
+        let err = Config::from_toml(
+            "[[flow]]\nfield = { kind = \"image\", path = \"wind.png\", origin = [0.0, 0.0], \
+             spacing = 10.0, max_speed = -2.0 }",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("flow #1 max_speed"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_breeding_that_starves_the_parent() {
+        let err = Config::from_toml(
+            "[[species]]\nname = \"fish\"\nbreeding = { min_energy = 50.0, cost = 50.0 }",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("fish.breeding.cost"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(matches!(
+            Config::from_toml("[world]\nradius = 5.0"),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+}
@@ -1,10 +1,38 @@
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
 
 use crate::vec::Vec2;
 
+/// Unique identifier for an entity. Handed out once at spawn time and never re-used.
+pub type EntityId = u64;
+
 /// Represents some template defining the constraints of an entity
 pub trait EntityTemplate {
     type Entity;
 
-    fn spawn<R: Rng>(&self, rng: &mut R, position: &Vec2, facing: f32) -> Self::Entity;
+    fn spawn<R: Rng>(
+        &self,
+        rng: &mut R,
+        id: EntityId,
+        position: &Vec2,
+        facing: f32,
+    ) -> Self::Entity;
+}
+
+/// Picks one of several templates at random, according to their relative weights
+pub struct WeightedTemplate<T: EntityTemplate>(pub Vec<(f32, T)>);
+
+impl<T: EntityTemplate> EntityTemplate for WeightedTemplate<T> {
+    type Entity = T::Entity;
+
+    fn spawn<R: Rng>(
+        &self,
+        rng: &mut R,
+        id: EntityId,
+        position: &Vec2,
+        facing: f32,
+    ) -> Self::Entity {
+        let template = &self.0.choose_weighted(rng, |item| item.0).unwrap().1;
+
+        template.spawn(rng, id, position, facing)
+    }
 }
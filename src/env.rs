@@ -0,0 +1,444 @@
+//! Gym-style reinforcement learning environment. One or more bois are driven by an agent, while
+//! the rest of the swarm carries on following the usual rules.
+//!
+//! ```no_run
+//! use boids::{config::Config, env::{Env, SurvivalReward}};
+//!
+//! let mut env = Env::new(Config::default(), "prey", 1, 1000, Box::new(SurvivalReward)).unwrap();
+//! let mut observation = env.reset(42).unwrap();
+//! loop {
+//!     let action = vec![0.; env.num_controlled()]; // Everyone heads east
+//!     let (next, reward, done, _info) = env.step(&action);
+//!     observation = next;
+//!     if done {
+//!         break;
+//!     }
+//! }
+//! ```
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    boi::Boi,
+    config::{Config, ConfigError},
+    entity::EntityId,
+    perception::Perception,
+    species::SpeciesId,
+    vec::Vec2,
+    world::{Kill, World},
+};
+
+/// Number of neighbours each controlled boi can see in its observation
+pub const MAX_NEIGHBOURS: usize = 8;
+
+/// How a neighbour relates to the observing boi
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Friend, // Same species
+    Food,   // Something we can eat
+    Threat, // Something that can eat us
+    Other,
+}
+
+/// What a controlled boi knows about one of its neighbours. Everything is relative to the
+/// observing boi, and rotated so +x is the way it's facing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NeighbourObservation {
+    pub offset: Vec2,
+    pub distance: f32,
+    pub heading: f32, // radians, relative to our own
+    pub relation: Relation,
+}
+
+/// What a single controlled boi can see
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentObservation {
+    pub alive: bool,
+    pub position: Vec2,
+    pub direction: f32,
+    pub speed: f32,
+    pub vision: f32,
+    pub boundary_distance: f32, // Distance to the arena edge, negative once outside it
+    pub neighbours: Vec<NeighbourObservation>, // Closest first, at most MAX_NEIGHBOURS
+}
+
+/// Values per neighbour slot in [AgentObservation::to_vec]
+const NEIGHBOUR_FEATURES: usize = 8;
+
+/// Length of [AgentObservation::to_vec]
+pub const AGENT_OBSERVATION_LEN: usize = 6 + MAX_NEIGHBOURS * NEIGHBOUR_FEATURES;
+
+impl AgentObservation {
+    /// Observation for a boi that's been gobbled up
+    fn dead() -> Self {
+        Self {
+            alive: false,
+            position: Vec2::new(0., 0.),
+            direction: 0.,
+            speed: 0.,
+            vision: 0.,
+            boundary_distance: 0.,
+            neighbours: vec![],
+        }
+    }
+
+    /// Flatten into a fixed length vector, with empty neighbour slots zeroed out. Distances are
+    /// scaled by vision so they're roughly in [-1, 1].
+    pub fn to_vec(&self) -> Vec<f32> {
+        let mut out = Vec::with_capacity(AGENT_OBSERVATION_LEN);
+        if !self.alive {
+            out.resize(AGENT_OBSERVATION_LEN, 0.);
+            return out;
+        }
+
+        out.extend([
+            1.,
+            self.direction.cos(),
+            self.direction.sin(),
+            self.speed,
+            self.vision,
+            self.boundary_distance / self.vision,
+        ]);
+        for neighbour in &self.neighbours {
+            out.extend([
+                neighbour.offset.x / self.vision,
+                neighbour.offset.y / self.vision,
+                neighbour.distance / self.vision,
+                neighbour.heading.cos(),
+                neighbour.heading.sin(),
+                (neighbour.relation == Relation::Friend) as u8 as f32,
+                (neighbour.relation == Relation::Food) as u8 as f32,
+                (neighbour.relation == Relation::Threat) as u8 as f32,
+            ]);
+        }
+        out.resize(AGENT_OBSERVATION_LEN, 0.);
+
+        out
+    }
+}
+
+/// One entry per controlled boi, in the same order as the actions
+pub type Observation = Vec<AgentObservation>;
+
+/// Desired heading in radians for each controlled boi. Entries for dead bois are ignored.
+pub type Action = [f32];
+
+/// Extra details about a step
+#[derive(Debug, Clone, Default)]
+pub struct Info {
+    pub tick: u32,
+    pub kills: Vec<Kill>, // Every kill in the world this step
+    pub truncated: bool,  // Ran out of time rather than everyone dying
+}
+
+/// Works out how well the controlled bois did over a step
+pub trait Reward: Send {
+    fn reward(&self, world: &World, agent: EntityId, alive: bool) -> f32;
+}
+
+/// +1 for every tick a controlled boi stays alive. Good for prey.
+pub struct SurvivalReward;
+
+impl Reward for SurvivalReward {
+    fn reward(&self, _world: &World, _agent: EntityId, alive: bool) -> f32 {
+        if alive {
+            1.
+        } else {
+            0.
+        }
+    }
+}
+
+/// +1 for every boi a controlled boi gobbles up. Good for predators.
+pub struct CatchReward;
+
+impl Reward for CatchReward {
+    fn reward(&self, world: &World, agent: EntityId, _alive: bool) -> f32 {
+        world
+            .kills
+            .iter()
+            .filter(|kill| kill.predator == agent)
+            .count() as f32
+    }
+}
+
+/// Something that went wrong while running an episode, as opposed to a bad scenario
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvError {
+    /// The seed didn't spawn enough bois of the controlled species to take over. Another seed
+    /// might.
+    NotEnoughBois {
+        seed: u64,
+        spawned: usize,
+        needed: usize,
+    },
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::NotEnoughBois {
+                seed,
+                spawned,
+                needed,
+            } => write!(
+                f,
+                "only {} bois of the controlled species spawned with seed {}, need {}",
+                spawned, seed, needed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+pub struct Env {
+    pub config: Config,
+    world: Option<World>, // Not made until the first reset
+    species: SpeciesId,   // Controlled bois are picked from this species
+    num_controlled: usize,
+    controlled: Vec<EntityId>,
+    reward: Box<dyn Reward>,
+    max_ticks: u32, // Episodes are cut off after this many ticks
+    tick: u32,
+}
+
+impl Env {
+    /// Set up an environment where `num_controlled` bois of the given species are driven by the
+    /// agent. Call [Env::reset] before stepping.
+    pub fn new(
+        config: Config,
+        species: &str,
+        num_controlled: usize,
+        max_ticks: u32,
+        reward: Box<dyn Reward>,
+    ) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let species = config
+            .species
+            .iter()
+            .position(|s| s.name == species)
+            .ok_or_else(|| {
+                ConfigError::Invalid(format!("there's no species called \"{}\"", species))
+            })?;
+
+        Ok(Self {
+            config,
+            world: None,
+            species,
+            num_controlled,
+            controlled: vec![],
+            reward,
+            max_ticks,
+            tick: 0,
+        })
+    }
+
+    pub fn num_controlled(&self) -> usize {
+        self.num_controlled
+    }
+
+    /// IDs of the bois being driven by the agent, in the same order as the actions
+    pub fn controlled(&self) -> &[EntityId] {
+        &self.controlled
+    }
+
+    /// The world the current episode is running in. None until the first [Env::reset].
+    pub fn world(&self) -> Option<&World> {
+        self.world.as_ref()
+    }
+
+    /// Start a new episode. Fails if the seed doesn't spawn enough bois of the controlled species
+    /// to take over.
+    pub fn reset(&mut self, seed: u64) -> Result<Observation, EnvError> {
+        let world = World::new(&self.config, seed);
+
+        // Take over the first few bois of the right species
+        let controlled = world
+            .bois
+            .iter()
+            .filter(|boi| boi.species == self.species)
+            .take(self.num_controlled)
+            .map(|boi| boi.id)
+            .collect::<Vec<_>>();
+        if controlled.len() < self.num_controlled {
+            return Err(EnvError::NotEnoughBois {
+                seed,
+                spawned: controlled.len(),
+                needed: self.num_controlled,
+            });
+        }
+
+        self.world = Some(world);
+        self.controlled = controlled;
+        self.tick = 0;
+        Ok(self.observe())
+    }
+
+    /// Advance one tick, with the controlled bois heading the way the action says. Panics if
+    /// [Env::reset] hasn't been called yet.
+    pub fn step(&mut self, action: &Action) -> (Observation, f32, bool, Info) {
+        assert_eq!(
+            action.len(),
+            self.controlled.len(),
+            "Need one action per controlled boi"
+        );
+
+        let overrides = self
+            .controlled
+            .iter()
+            .zip(action)
+            .map(|(id, heading)| {
+                (
+                    *id,
+                    Vec2 {
+                        x: heading.cos(),
+                        y: heading.sin(),
+                    },
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let world = self.world.as_mut().expect("Call reset before stepping");
+        world.step_with(1. / self.config.world.tick_rate as f32, &overrides);
+        self.tick += 1;
+
+        let alive = self
+            .controlled
+            .iter()
+            .map(|id| world.bois.iter().any(|boi| boi.id == *id))
+            .collect::<Vec<_>>();
+        let reward = self
+            .controlled
+            .iter()
+            .zip(&alive)
+            .map(|(id, alive)| self.reward.reward(world, *id, *alive))
+            .sum();
+
+        let truncated = self.tick >= self.max_ticks;
+        let done = truncated || !alive.iter().any(|alive| *alive);
+        let info = Info {
+            tick: self.tick,
+            kills: world.kills.clone(),
+            truncated,
+        };
+
+        (self.observe(), reward, done, info)
+    }
+
+    fn observe(&self) -> Observation {
+        let Some(world) = &self.world else {
+            return vec![];
+        };
+        self.controlled
+            .iter()
+            .map(|id| match world.bois.iter().find(|boi| boi.id == *id) {
+                Some(boi) => observe_boi(world, boi),
+                None => AgentObservation::dead(),
+            })
+            .collect()
+    }
+}
+
+/// Build a boi's observation from the same neighbour query it would use to decide
+fn observe_boi(world: &World, boi: &Boi) -> AgentObservation {
+    let mut perception = Perception::of(world, boi);
+    perception
+        .neighbours
+        .sort_by(|n1, n2| n1.distance.total_cmp(&n2.distance));
+
+    let (cos, sin) = (boi.direction.cos(), boi.direction.sin());
+    let neighbours = perception
+        .neighbours
+        .iter()
+        .take(MAX_NEIGHBOURS)
+        .map(|neighbour| {
+            let relation = if neighbour.species == boi.species {
+                Relation::Friend
+            } else if neighbour.prey {
+                Relation::Food
+            } else if neighbour.predator {
+                Relation::Threat
+            } else {
+                Relation::Other
+            };
+
+            // Rotate into the boi's frame of reference
+            let offset = neighbour.offset;
+            NeighbourObservation {
+                offset: Vec2::new(
+                    offset.x * cos + offset.y * sin,
+                    offset.y * cos - offset.x * sin,
+                ),
+                distance: neighbour.distance,
+                heading: neighbour.heading - boi.direction,
+                relation,
+            }
+        })
+        .collect();
+
+    AgentObservation {
+        alive: true,
+        position: boi.position,
+        direction: boi.direction,
+        speed: perception.speed,
+        vision: perception.vision,
+        boundary_distance: perception.boundary.edge_distance(),
+        neighbours,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn episode_runs_to_completion() {
+        let mut config = Config::default();
+        config.world.num_bois = 200;
+
+        let mut env = Env::new(config, "prey", 3, 50, Box::new(SurvivalReward)).unwrap();
+        assert!(env.world().is_none());
+        let observation = env.reset(7).unwrap();
+        assert_eq!(observation.len(), 3);
+
+        let mut total_reward = 0.;
+        let mut steps = 0;
+        loop {
+            let (observation, reward, done, info) = env.step(&[0., 1., 2.]);
+            observation
+                .iter()
+                .for_each(|agent| assert_eq!(agent.to_vec().len(), AGENT_OBSERVATION_LEN));
+            total_reward += reward;
+            steps += 1;
+            if done {
+                assert!(info.truncated || observation.iter().all(|agent| !agent.alive));
+                break;
+            }
+        }
+
+        assert!(steps <= 50);
+        assert!(total_reward > 0. && total_reward <= 3. * steps as f32);
+    }
+
+    #[test]
+    fn not_enough_bois_to_control() {
+        let mut config = Config::default();
+        config.world.num_bois = 3;
+
+        let mut env = Env::new(config, "predator", 3, 50, Box::new(CatchReward)).unwrap();
+        let Err(EnvError::NotEnoughBois {
+            spawned, needed, ..
+        }) = env.reset(7)
+        else {
+            panic!("Took over bois that don't exist");
+        };
+        assert!(spawned < 3);
+        assert_eq!(needed, 3);
+        assert!(env.world().is_none());
+    }
+
+    #[test]
+    fn unknown_species() {
+        assert!(Env::new(Config::default(), "dragon", 1, 10, Box::new(CatchReward)).is_err());
+    }
+}
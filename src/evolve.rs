@@ -0,0 +1,306 @@
+//! Evolves a species' brain with a genetic algorithm. Every genome in the population gets to
+//! drive the whole species through the same few headless episodes, and the fittest ones are
+//! carried over & bred to make the next generation.
+
+use std::collections::HashSet;
+
+use rand::prelude::*;
+use rand_distr::Normal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    brain::{BrainConfig, Genome},
+    config::{Config, ConfigError},
+    par,
+    species::SpeciesId,
+    world::{SimRng, World},
+};
+
+/// How to evolve a brain, as it's written in the scenario file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvolutionConfig {
+    pub species: String, // Who gets the brains. Everyone else sticks to their rules.
+    pub hidden: Vec<usize>, // Neurons in each hidden layer
+    pub population: usize,
+    pub generations: usize, // How many to run for with --evolve
+    pub episodes: usize,    // Headless runs each genome is scored over, averaged
+    pub ticks: u32,         // Length of each episode
+    pub elites: usize,      // Fittest genomes carried over untouched, & saved after each generation
+    pub mutation: f32,      // Standard deviation of the noise added to a weight
+    pub mutation_rate: f32, // Chance of each weight getting noise added, 0 to 1
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        Self {
+            species: "prey".into(),
+            hidden: vec![8],
+            population: 30,
+            generations: 20,
+            episodes: 2,
+            ticks: 600,
+            elites: 5,
+            mutation: 0.2,
+            mutation_rate: 0.1,
+        }
+    }
+}
+
+/// A population of genomes part way through being evolved
+pub struct Evolution {
+    config: Config,
+    settings: EvolutionConfig,
+    species: SpeciesId,
+    population: Vec<Genome>,
+    rng: SimRng,
+    seed: u64,
+    pub generation: usize, // Number of generations scored so far
+}
+
+impl Evolution {
+    /// Start evolving the brain for the scenario's `[evolution]` species. If the species already
+    /// has a brain of the right shape, the first generation is made from it, otherwise it's random.
+    pub fn new(config: &Config, seed: u64) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let settings = config.evolution.clone().ok_or_else(|| {
+            ConfigError::Invalid("the scenario has no [evolution] section".into())
+        })?;
+        let species = config
+            .species
+            .iter()
+            .position(|s| s.name == settings.species)
+            .ok_or_else(|| {
+                ConfigError::Invalid(format!(
+                    "there's no species called \"{}\"",
+                    settings.species
+                ))
+            })?;
+        let mut rng = SimRng::seed_from_u64(seed);
+
+        let population = match &config.species[species].brain {
+            Some(BrainConfig::Genome(genome)) if genome.hidden == settings.hidden => {
+                let mut population = vec![genome.clone()];
+                while population.len() < settings.population {
+                    population.push(mutate(genome, &settings, &mut rng));
+                }
+                population
+            }
+            _ => (0..settings.population)
+                .map(|_| Genome::random(&settings.hidden, &mut rng))
+                .collect(),
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            settings,
+            species,
+            population,
+            rng,
+            seed,
+            generation: 0,
+        })
+    }
+
+    /// How well the species does following its rules instead, on the same episodes the current
+    /// generation gets scored on. Runs them all over again, so only ask when it's worth it.
+    pub fn baseline(&self) -> f32 {
+        self.score(None)
+    }
+
+    /// Average fitness over this generation's episodes. Everyone's scored on the same ones so it's
+    /// a fair fight.
+    fn score(&self, brain: Option<&Genome>) -> f32 {
+        let episodes = self.settings.episodes;
+        (0..episodes)
+            .map(|episode| {
+                let seed = self
+                    .seed
+                    .wrapping_add((self.generation * episodes + episode) as u64);
+                fitness(&self.config, self.species, brain, seed, self.settings.ticks)
+            })
+            .sum::<f32>()
+            / episodes as f32
+    }
+
+    /// Score the current generation & breed the next one from it. Returns the elites, fittest
+    /// first, with their scores.
+    pub fn step(&mut self) -> Vec<Genome> {
+        let scores = par::map(&self.population, |genome| self.score(Some(genome)));
+
+        let mut scored = self.population.drain(..).zip(scores).collect::<Vec<_>>();
+        scored
+            .iter_mut()
+            .for_each(|(genome, score)| genome.fitness = *score);
+        scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let ranked = scored
+            .into_iter()
+            .map(|(genome, _)| genome)
+            .collect::<Vec<_>>();
+
+        // Elites go through as they are, the rest are bred from tournament winners
+        let elites = ranked[..self.settings.elites].to_vec();
+        let mut next = elites.clone();
+        while next.len() < self.settings.population {
+            let mum = tournament(&ranked, &mut self.rng);
+            let dad = tournament(&ranked, &mut self.rng);
+            let child = crossover(mum, dad, &mut self.rng);
+            next.push(mutate(&child, &self.settings, &mut self.rng));
+        }
+
+        self.population = next;
+        self.generation += 1;
+        elites
+    }
+}
+
+/// How well `species` does over one headless episode, steered by a genome or by its rules if
+/// there isn't one. Counts how much of the species is still around on average, relative to how
+/// many there were to start with, plus how many bois it ate per head.
+pub fn fitness(
+    config: &Config,
+    species: SpeciesId,
+    brain: Option<&Genome>,
+    seed: u64,
+    ticks: u32,
+) -> f32 {
+    let mut config = config.clone();
+    config.species[species].brain = brain.map(|genome| BrainConfig::Genome(genome.clone()));
+    let mut world = World::new(&config, seed);
+    let dt = 1. / config.world.tick_rate as f32;
+
+    let count = |world: &World| world.bois.iter().filter(|b| b.species == species).count();
+    let initial = count(&world);
+    if initial == 0 || ticks == 0 {
+        return 0.;
+    }
+
+    let (mut alive, mut kills) = (0, 0);
+    for _ in 0..ticks {
+        world.step(dt);
+        alive += count(&world);
+
+        let ours = world
+            .bois
+            .iter()
+            .filter(|boi| boi.species == species)
+            .map(|boi| boi.id)
+            .collect::<HashSet<_>>();
+        kills += world
+            .kills
+            .iter()
+            .filter(|kill| ours.contains(&kill.predator))
+            .count();
+    }
+
+    (alive as f32 / ticks as f32 + kills as f32) / initial as f32
+}
+
+/// Fittest of a few genomes picked at random. Assumes they're ranked fittest first.
+fn tournament<'a, R: Rng>(ranked: &'a [Genome], rng: &mut R) -> &'a Genome {
+    let best = (0..3)
+        .map(|_| rng.gen_range(0..ranked.len()))
+        .min()
+        .unwrap();
+    &ranked[best]
+}
+
+/// Each weight comes from either parent at random
+fn crossover<R: Rng>(mum: &Genome, dad: &Genome, rng: &mut R) -> Genome {
+    Genome {
+        hidden: mum.hidden.clone(),
+        weights: mum
+            .weights
+            .iter()
+            .zip(&dad.weights)
+            .map(|(m, d)| if rng.gen() { *m } else { *d })
+            .collect(),
+        fitness: 0.,
+    }
+}
+
+fn mutate<R: Rng>(genome: &Genome, settings: &EvolutionConfig, rng: &mut R) -> Genome {
+    let noise = Normal::new(0., settings.mutation).expect("Mutation should be validated");
+    Genome {
+        hidden: genome.hidden.clone(),
+        weights: genome
+            .weights
+            .iter()
+            .map(|weight| {
+                if rng.gen::<f32>() < settings.mutation_rate {
+                    weight + noise.sample(rng)
+                } else {
+                    *weight
+                }
+            })
+            .collect(),
+        fitness: 0.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.world.num_bois = 60;
+        config.world.arena_radius = 30.;
+        config.evolution = Some(EvolutionConfig {
+            hidden: vec![4],
+            population: 6,
+            episodes: 1,
+            ticks: 30,
+            elites: 2,
+            ..Default::default()
+        });
+        config
+    }
+
+    #[test]
+    fn evolving_is_reproducible() {
+        let config = config();
+        let run = || {
+            let mut evolution = Evolution::new(&config, 5).unwrap();
+            (0..3).map(|_| evolution.step()).last().unwrap()
+        };
+        let elites = run();
+        assert_eq!(elites.len(), 2);
+        assert!(elites[0].fitness >= elites[1].fitness);
+        assert_eq!(elites, run());
+    }
+
+    #[test]
+    fn needs_an_evolution_section() {
+        let config = Config {
+            evolution: None,
+            ..config()
+        };
+        let Err(err) = Evolution::new(&config, 1) else {
+            panic!("Evolved without an [evolution] section");
+        };
+        assert!(err.to_string().contains("[evolution]"), "{}", err);
+    }
+
+    #[test]
+    fn elites_survive_a_round_trip() {
+        let mut config = config();
+        let elites = Evolution::new(&config, 1).unwrap().step();
+
+        let path = std::env::temp_dir().join(format!("bois-genomes-{}.json", std::process::id()));
+        Genome::save(&path, &elites).unwrap();
+        let mut brain = BrainConfig::File {
+            path: path.file_name().unwrap().to_string_lossy().into(),
+        };
+        brain.load_file(path.parent().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Picks up where it left off, starting from the fittest
+        let BrainConfig::Genome(genome) = brain else {
+            panic!("Brain wasn't loaded");
+        };
+        assert_eq!(genome, elites[0]);
+        config.species[1].brain = Some(BrainConfig::Genome(genome.clone()));
+        assert_eq!(Evolution::new(&config, 1).unwrap().population[0], genome);
+    }
+}
@@ -0,0 +1,329 @@
+//! Wind & currents that carry bois along on top of however they're moving themselves
+
+use std::{f32::consts::PI, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec2;
+
+/// A single flow field as it's written in the scenario file. All the fields in a scenario are
+/// added together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowConfig {
+    pub field: FieldConfig,
+    pub gust: Option<Gust>, // Steady if not given
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FieldConfig {
+    /// The same everywhere
+    Uniform { velocity: [f32; 2] },
+    /// Swirls around a point, anticlockwise on screen for positive strength. Spins like a solid
+    /// disc inside `radius`, then dies off with distance outside it.
+    Vortex {
+        centre: [f32; 2],
+        radius: f32,
+        strength: f32, // Flow speed at `radius`
+    },
+    /// Velocities at evenly spaced points, row by row, blended in between. Anywhere off the edge
+    /// of the grid gets whatever's at the nearest edge.
+    Grid {
+        origin: [f32; 2], // Where the first point is
+        spacing: f32,
+        width: usize, // Points per row
+        velocities: Vec<[f32; 2]>,
+    },
+    /// A grid read from an image, one point per pixel. Red is the x velocity & green is the y
+    /// velocity, with 128 being still and 1 & 255 being `max_speed` either way (0 counts as 1). Turned into a
+    /// [FieldConfig::Grid] when the scenario is loaded, with the path relative to the scenario.
+    Image {
+        path: String,
+        origin: [f32; 2], // Where the top left pixel is
+        spacing: f32,
+        max_speed: f32,
+    },
+}
+
+impl FieldConfig {
+    /// Read in an image field, turning it into a grid. Anything else stays as it is.
+    pub fn load_image(&mut self, dir: &Path) -> Result<(), image::ImageError> {
+        let FieldConfig::Image {
+            path,
+            origin,
+            spacing,
+            max_speed,
+        } = self
+        else {
+            return Ok(());
+        };
+
+        let image = image::open(dir.join(&*path))?.into_rgb8();
+        let speed = |channel: u8| ((channel as f32 - 128.) / 127.).max(-1.) * *max_speed;
+        *self = FieldConfig::Grid {
+            origin: *origin,
+            spacing: *spacing,
+            width: image.width() as usize,
+            velocities: image
+                .pixels()
+                .map(|pixel| [speed(pixel[0]), speed(pixel[1])])
+                .collect(),
+        };
+
+        Ok(())
+    }
+}
+
+/// Makes a field come & go over time. Strength swings between `1 - strength` & `1 + strength`
+/// times the field's usual strength, and the direction swings by up to `swing` radians either way.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gust {
+    pub period: f32, // Seconds
+    pub strength: f32,
+    pub swing: f32,
+}
+
+impl Default for Gust {
+    fn default() -> Self {
+        Self {
+            period: 10.,
+            strength: 0.5,
+            swing: 0.,
+        }
+    }
+}
+
+impl Gust {
+    fn apply(&self, velocity: &Vec2, time: f32) -> Vec2 {
+        let phase = (2. * PI * time / self.period).sin();
+        let scale = 1. + self.strength * phase;
+        let (sin, cos) = (self.swing * phase).sin_cos();
+        Vec2::new(
+            (velocity.x * cos - velocity.y * sin) * scale,
+            (velocity.x * sin + velocity.y * cos) * scale,
+        )
+    }
+}
+
+enum Field {
+    Uniform(Vec2),
+    Vortex {
+        centre: Vec2,
+        radius: f32,
+        strength: f32,
+    },
+    Grid {
+        origin: Vec2,
+        spacing: f32,
+        width: usize,
+        height: usize,
+        velocities: Vec<Vec2>,
+    },
+}
+
+impl Field {
+    /// Image fields need loading first, until then they're still
+    fn from_config(config: &FieldConfig) -> Self {
+        match config {
+            FieldConfig::Uniform { velocity: [x, y] } => Field::Uniform(Vec2::new(*x, *y)),
+            FieldConfig::Vortex {
+                centre: [x, y],
+                radius,
+                strength,
+            } => Field::Vortex {
+                centre: Vec2::new(*x, *y),
+                radius: *radius,
+                strength: *strength,
+            },
+            FieldConfig::Grid {
+                origin: [x, y],
+                spacing,
+                width,
+                velocities,
+            } => Field::Grid {
+                origin: Vec2::new(*x, *y),
+                spacing: *spacing,
+                width: *width,
+                height: velocities.len() / width,
+                velocities: velocities.iter().map(|[x, y]| Vec2::new(*x, *y)).collect(),
+            },
+            FieldConfig::Image { .. } => Field::Uniform(Vec2::new(0., 0.)),
+        }
+    }
+
+    fn at(&self, point: &Vec2) -> Vec2 {
+        match self {
+            Field::Uniform(velocity) => *velocity,
+            Field::Vortex {
+                centre,
+                radius,
+                strength,
+            } => {
+                let offset = point.sub(centre);
+                let distance = offset.distance(&Vec2::new(0., 0.));
+                let speed = if distance < *radius {
+                    strength * distance / radius
+                } else {
+                    strength * radius / distance
+                };
+                // Screen y points down, so this is anticlockwise on screen
+                Vec2::new(offset.y, -offset.x).normalise().mul(speed)
+            }
+            Field::Grid {
+                origin,
+                spacing,
+                width,
+                height,
+                velocities,
+            } => {
+                // Position in grid points, kept on the grid
+                let grid = point.sub(origin).div(*spacing);
+                let x = grid.x.clamp(0., (width - 1) as f32);
+                let y = grid.y.clamp(0., (height - 1) as f32);
+                let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+                let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+                let at = |x: usize, y: usize| velocities[y * width + x];
+
+                // Bilinear blend of the four corners around the point
+                let top = at(x0, y0).lerp(&at(x1, y0), x - x0 as f32);
+                let bottom = at(x0, y1).lerp(&at(x1, y1), x - x0 as f32);
+                top.lerp(&bottom, y - y0 as f32)
+            }
+        }
+    }
+}
+
+/// Every flow field in the world, added together
+pub struct Flow {
+    fields: Vec<(Field, Option<Gust>)>,
+}
+
+impl Flow {
+    pub fn from_config(configs: &[FlowConfig]) -> Self {
+        Self {
+            fields: configs
+                .iter()
+                .map(|config| (Field::from_config(&config.field), config.gust))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Velocity of the flow at a point, `time` seconds into the simulation
+    pub fn at(&self, point: &Vec2, time: f32) -> Vec2 {
+        self.fields
+            .iter()
+            .map(|(field, gust)| {
+                let velocity = field.at(point);
+                match gust {
+                    Some(gust) => gust.apply(&velocity, time),
+                    None => velocity,
+                }
+            })
+            .fold(Vec2::new(0., 0.), |total, velocity| total.add(&velocity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(&b) < 1e-5
+    }
+
+    #[test]
+    fn vortex() {
+        let flow = Flow::from_config(&[FlowConfig {
+            field: FieldConfig::Vortex {
+                centre: [10., 0.],
+                radius: 2.,
+                strength: 4.,
+            },
+            gust: None,
+        }]);
+
+        // Solid inside, dying off outside
+        assert!(close(flow.at(&Vec2::new(11., 0.), 0.), Vec2::new(0., -2.)));
+        assert!(close(flow.at(&Vec2::new(12., 0.), 0.), Vec2::new(0., -4.)));
+        assert!(close(flow.at(&Vec2::new(10., 4.), 0.), Vec2::new(2., 0.)));
+        assert!(close(flow.at(&Vec2::new(10., 0.), 0.), Vec2::new(0., 0.)));
+    }
+
+    #[test]
+    fn grid_blends_and_clamps() {
+        let flow = Flow::from_config(&[FlowConfig {
+            field: FieldConfig::Grid {
+                origin: [0., 0.],
+                spacing: 10.,
+                width: 2,
+                velocities: vec![[0., 0.], [4., 0.], [0., 4.], [4., 4.]],
+            },
+            gust: None,
+        }]);
+
+        assert!(close(flow.at(&Vec2::new(5., 5.), 0.), Vec2::new(2., 2.)));
+        assert!(close(flow.at(&Vec2::new(2.5, 0.), 0.), Vec2::new(1., 0.)));
+        assert!(close(
+            flow.at(&Vec2::new(-50., 100.), 0.),
+            Vec2::new(0., 4.)
+        ));
+    }
+
+    #[test]
+    fn gusts_come_and_go() {
+        let flow = Flow::from_config(&[
+            FlowConfig {
+                field: FieldConfig::Uniform { velocity: [2., 0.] },
+                gust: Some(Gust {
+                    period: 4.,
+                    strength: 0.5,
+                    swing: PI / 2.,
+                }),
+            },
+            FlowConfig {
+                field: FieldConfig::Uniform { velocity: [0., 1.] },
+                gust: None,
+            },
+        ]);
+
+        assert!(close(flow.at(&Vec2::new(0., 0.), 0.), Vec2::new(2., 1.)));
+        // Peak of the gust, strongest & swung round a quarter turn
+        assert!(close(flow.at(&Vec2::new(0., 0.), 1.), Vec2::new(0., 4.)));
+        assert!(close(flow.at(&Vec2::new(0., 0.), 3.), Vec2::new(0., 0.)));
+    }
+
+    #[test]
+    fn image_becomes_grid() {
+        let dir = std::env::temp_dir().join(format!("bois-flow-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut image = image::RgbImage::new(3, 1);
+        image.put_pixel(0, 0, image::Rgb([255, 128, 0]));
+        image.put_pixel(1, 0, image::Rgb([1, 128, 0]));
+        image.put_pixel(2, 0, image::Rgb([128, 0, 0]));
+        image.save(dir.join("wind.png")).unwrap();
+
+        let mut config = FieldConfig::Image {
+            path: "wind.png".into(),
+            origin: [0., 0.],
+            spacing: 1.,
+            max_speed: 3.,
+        };
+        config.load_image(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let FieldConfig::Grid {
+            width, velocities, ..
+        } = config
+        else {
+            panic!("Image wasn't loaded");
+        };
+        assert_eq!(width, 3);
+        assert_eq!(velocities, vec![[3., 0.], [-3., 0.], [0., -3.]]);
+    }
+}
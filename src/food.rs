@@ -0,0 +1,215 @@
+//! A layer of food over the arena that grazers eat & that grows back over time
+
+use serde::{Deserialize, Serialize};
+
+use crate::{species::SpeciesId, vec::Vec2};
+
+/// How the food grows & who eats it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoodConfig {
+    pub cell_size: f32,       // World units along each side of a cell
+    pub capacity: f32,        // Most food a single cell can hold
+    pub initial: f32,         // Fraction of capacity each cell starts with
+    pub growth_rate: f32,     // Logistic growth rate, per second
+    pub reserve: f32,         // Fraction of capacity that can't be grazed, so bare cells grow back
+    pub grazers: Vec<String>, // Names of the species that eat it
+    pub bite: f32,            // Food a grazer eats per second from the cell it's over
+    pub nutrition: f32,       // Energy gained per unit of food eaten
+}
+
+impl Default for FoodConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 5.,
+            capacity: 10.,
+            initial: 1.,
+            growth_rate: 0.1,
+            reserve: 0.01,
+            grazers: vec![],
+            bite: 5.,
+            nutrition: 1.,
+        }
+    }
+}
+
+/// Square grid of food covering the whole arena
+pub struct Food {
+    pub config: FoodConfig,
+    grazers: Vec<bool>, // By species
+    origin: Vec2,       // Top left corner of the grid
+    width: usize,       // Cells along each side
+    cells: Vec<f32>,    // Row by row
+}
+
+impl Food {
+    /// Assumes the config has already been validated, so every grazer is a real species
+    pub fn new(config: &FoodConfig, species: &[String], centre: &Vec2, radius: f32) -> Self {
+        let width = (2. * radius / config.cell_size).ceil() as usize;
+        let half = width as f32 * config.cell_size / 2.;
+
+        Self {
+            config: config.clone(),
+            grazers: species
+                .iter()
+                .map(|name| config.grazers.contains(name))
+                .collect(),
+            origin: centre.add_scalar(-half),
+            width,
+            cells: vec![config.capacity * config.initial; width * width],
+        }
+    }
+
+    pub fn grazes(&self, species: SpeciesId) -> bool {
+        self.grazers[species]
+    }
+
+    pub fn cells(&self) -> &[f32] {
+        &self.cells
+    }
+
+    /// Put back food saved with [Food::cells]. Ignored if it's for a different sized grid.
+    pub fn restore(&mut self, cells: Vec<f32>) {
+        if cells.len() == self.cells.len() {
+            self.cells = cells;
+        }
+    }
+
+    /// All the food there is
+    pub fn total(&self) -> f32 {
+        self.cells.iter().sum()
+    }
+
+    /// Each cell's centre & how full it is, from 0 to 1
+    pub fn iter(&self) -> impl Iterator<Item = (Vec2, f32)> + '_ {
+        self.cells.iter().enumerate().map(|(i, food)| {
+            (
+                self.centre(i % self.width, i / self.width),
+                food / self.config.capacity,
+            )
+        })
+    }
+
+    fn centre(&self, x: usize, y: usize) -> Vec2 {
+        self.origin
+            .add(&Vec2::new(x as f32, y as f32).mul(self.config.cell_size))
+            .add_scalar(self.config.cell_size / 2.)
+    }
+
+    /// Cell a point is in, if it's on the grid
+    fn cell(&self, point: &Vec2) -> Option<usize> {
+        let grid = point.sub(&self.origin).div(self.config.cell_size);
+        let (x, y) = (grid.x.floor(), grid.y.floor());
+        let size = self.width as f32;
+        (x >= 0. && y >= 0. && x < size && y < size).then(|| y as usize * self.width + x as usize)
+    }
+
+    /// Which way the food is, from a point, looking as far as `radius`. Comes out longer the more
+    /// lopsided the food is, up to 1 if it's all in one direction. None if there's none in sight.
+    pub fn scent(&self, point: &Vec2, radius: f32) -> Option<Vec2> {
+        let grid = point.sub(&self.origin).div(self.config.cell_size);
+        let reach = radius / self.config.cell_size;
+        let last = self.width as f32 - 1.;
+        let range = |centre: f32| {
+            let min = (centre - reach).floor().clamp(0., last) as usize;
+            let max = (centre + reach).floor().clamp(0., last) as usize;
+            min..=max
+        };
+
+        let (mut total, mut direction) = (0., Vec2::new(0., 0.));
+        for y in range(grid.y) {
+            for x in range(grid.x) {
+                let offset = self.centre(x, y).sub(point);
+                if offset.distance(&Vec2::new(0., 0.)) > radius {
+                    continue;
+                }
+                let food = self.cells[y * self.width + x];
+                total += food;
+                direction = direction.add(&offset.normalise().mul(food));
+            }
+        }
+
+        (total > 0.).then(|| direction.div(total))
+    }
+
+    /// Eat up to `amount` from wherever `point` is. Returns how much actually got eaten.
+    pub fn eat(&mut self, point: &Vec2, amount: f32) -> f32 {
+        let reserve = self.config.capacity * self.config.reserve;
+        let Some(cell) = self.cell(point) else {
+            return 0.;
+        };
+        let food = &mut self.cells[cell];
+        let eaten = amount.min(*food - reserve).max(0.);
+        *food -= eaten;
+        eaten
+    }
+
+    /// Logistic regrowth over `dt` seconds
+    pub fn grow(&mut self, dt: f32) {
+        let (rate, capacity) = (self.config.growth_rate, self.config.capacity);
+        self.cells.iter_mut().for_each(|food| {
+            *food = (*food + rate * *food * (1. - *food / capacity) * dt).min(capacity)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn food() -> Food {
+        let config = FoodConfig {
+            cell_size: 1.,
+            grazers: vec!["prey".into()],
+            ..Default::default()
+        };
+        Food::new(
+            &config,
+            &["predator".into(), "prey".into()],
+            &Vec2::new(0., 0.),
+            5.,
+        )
+    }
+
+    #[test]
+    fn grazing_and_regrowing() {
+        let mut food = food();
+        assert!(food.grazes(1) && !food.grazes(0));
+        assert_eq!(food.total(), 100. * 10.);
+
+        // Can't graze right down to nothing
+        let point = Vec2::new(0.5, 0.5);
+        assert_eq!(food.eat(&point, 4.), 4.);
+        assert!((food.eat(&point, 100.) - 5.9).abs() < 1e-5);
+        assert_eq!(food.eat(&point, 1.), 0.);
+        assert_eq!(food.eat(&Vec2::new(50., 0.), 1.), 0.);
+
+        // Grows back slowly at first, then quicker, never past capacity
+        let mut growth = vec![];
+        for _ in 0..200 {
+            let before = food.cells[food.cell(&point).unwrap()];
+            food.grow(1.);
+            growth.push(food.cells[food.cell(&point).unwrap()] - before);
+        }
+        assert!(growth[0] < growth[40]);
+        assert!((food.cells[food.cell(&point).unwrap()] - 10.).abs() < 1e-3);
+        assert!(food.cells.iter().all(|&f| f <= 10.));
+    }
+
+    #[test]
+    fn scent_points_to_food() {
+        let mut food = food();
+        let point = Vec2::new(0., 0.);
+        // Nothing to choose between
+        assert!(food.scent(&point, 3.).unwrap().distance(&Vec2::new(0., 0.)) < 1e-5);
+
+        // Graze the left side bare
+        for x in -5..0 {
+            for y in -5..5 {
+                food.eat(&Vec2::new(x as f32 + 0.5, y as f32 + 0.5), 100.);
+            }
+        }
+        let scent = food.scent(&point, 3.).unwrap();
+        assert!(scent.x > 0.3 && scent.y.abs() < 1e-5);
+    }
+}
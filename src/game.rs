@@ -1,239 +1,300 @@
 use crate::{
-    boi::{Boi, BoiTemplate, Species},
-    nest::Nest,
-    render::{Assets, RenderState},
-    strategy::Strategy,
+    boi::Boi,
+    config::{Config, RenderConfig},
+    entity::EntityId,
+    metrics::OrderParameters,
+    render::{self, BoiSprite, RenderState, Scenery},
+    replay::Recorder,
+    stats::{StatsWriter, TickStats},
     vec::Vec2,
+    world::World,
 };
-use std::f32::consts::PI;
+use std::{collections::HashMap, path::PathBuf};
 
-use geo_index::kdtree::{KDTree, KDTreeBuilder, KDTreeIndex};
 use ggez::{
     event::EventHandler,
-    graphics::{self, Color, DrawParam, Drawable},
+    graphics::{self, Color, DrawParam, Text},
+    input::{
+        keyboard::{KeyCode, KeyInput},
+        mouse::MouseButton,
+    },
     Context, GameResult,
 };
-use rand::{distributions::Uniform, prelude::*};
+
+/// How close to a boi a click has to be to select it, in world units
+const SELECT_RADIUS: f32 = 3.;
+
+/// Where the quicksave hotkeys save to & load from
+const QUICKSAVE_PATH: &str = "quicksave.bois";
+
+/// Don't try to catch up on more than this many ticks in one frame, otherwise a slow frame means
+/// more ticks next frame, which means an even slower frame...
+const MAX_TICKS_PER_FRAME: u32 = 20;
 
 pub struct MainState {
     // Game state stuff
-    pub bois: Vec<Boi>,
-    pub boi_tree: KDTree<f32>,
-    pub arena_centre: Vec2,
-    pub arena_radius: f32,
+    pub world: World,
+    tick_length: f32,                         // Seconds of simulated time per tick
+    time_scale: f32,                          // Seconds of simulated time per real second
+    accumulator: f32,                         // Simulated time that hasn't been ticked yet
+    previous: HashMap<EntityId, (Vec2, f32)>, // Position & direction of each boi as of the last tick
+    recorder: Option<(Recorder, PathBuf)>,
+    stats: Option<StatsWriter>,
 
     // Rendering stuff
+    render_config: RenderConfig,
     render: RenderState,
+    show_metrics: bool,
+    show_flow: bool,
+    selected: Option<EntityId>, // Boi whose sight lines are drawn
 }
 
 impl MainState {
-    pub fn new(
-        ctx: &mut Context,
-        arena_radius: f32,
-        num_bois: usize,
-        screen_scale: f32,
-        fps: u32,
-        padding: f32,
-    ) -> GameResult<Self> {
-        // Load all the assets once at the start
-        let mesh_raster_scale = 100.;
-        let assets = Assets::load(ctx, mesh_raster_scale, arena_radius)?;
-        let render = RenderState {
-            assets,
-            screen_scale,
-            padding,
-            fps,
-            needs_render: true,
-            mesh_raster_scale,
-        };
-
-        // Spawn a bunch of Bois
-        let arena_centre = Vec2 { x: 0., y: 0. };
-        let mut nest = Nest {
-            rng: thread_rng(),
-            pos: Uniform::new(-arena_radius, arena_radius),
-            direction: Uniform::new(0., 2. * PI),
-            template: BoiTemplate {
-                speed: Uniform::new(2., 3.),
-                vision: Uniform::new(2., 10.),
-                turning_speed: Uniform::new(0.1, 0.5),
-            },
-        };
-
-        let bois = (0..num_bois).map(|_| nest.spawn()).collect::<Vec<_>>();
-
-        // Build a K-D tree of the bois
-        let mut tree_builder = KDTreeBuilder::new(bois.len() as u32);
-        bois.iter().for_each(|boi| {
-            tree_builder.add(boi.position.x, boi.position.y);
-        });
-        let boi_tree = tree_builder.finish();
+    pub fn new(ctx: &mut Context, config: &Config, world: World) -> GameResult<Self> {
+        let render = RenderState::new(ctx, &config.render, &Scenery::of(&world))?;
 
         Ok(Self {
-            bois,
-            arena_radius,
-            arena_centre,
+            tick_length: 1. / world.config.world.tick_rate as f32,
+            world,
+            time_scale: config.render.time_scale,
+            accumulator: 0.,
+            previous: HashMap::new(),
+            recorder: None,
+            stats: None,
+            render_config: config.render.clone(),
             render,
-            boi_tree,
+            show_metrics: config.render.show_metrics,
+            show_flow: config.render.show_flow,
+            selected: None,
         })
     }
-    /// Converts a position in world space to canvas space
-    fn world_to_canvas(&self, vec: &Vec2) -> Vec2 {
-        vec.add_scalar(self.arena_radius)
-            .mul(self.render.screen_scale)
-            .add_scalar(self.render.padding)
+
+    /// Record everything that happens to a replay file, which gets written when the window closes
+    pub fn record_to(&mut self, path: PathBuf) {
+        self.recorder = Some((Recorder::new(&self.world), path));
+    }
+
+    /// Write stats out after every tick
+    pub fn write_stats_to(&mut self, stats: StatsWriter) {
+        self.stats = Some(stats);
+    }
+
+    /// Where a boi should be drawn, somewhere between the last tick & this one
+    fn interpolated(&self, boi: &Boi, alpha: f32) -> (Vec2, f32) {
+        let current = (boi.position, boi.direction);
+        match self.previous.get(&boi.id) {
+            Some(previous) => render::interpolate(*previous, current, alpha),
+            // Only just spawned
+            None => current,
+        }
     }
 }
 
 impl EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
-        while ctx.time.check_update_time(self.render.fps) {
-            println!("--- Upate ---");
+        self.accumulator += ctx.time.delta().as_secs_f32() * self.time_scale;
 
-            //Build a K-D tree of the bois
-            self.boi_tree = self
-                .bois
-                .iter()
-                .fold(
-                    KDTreeBuilder::new(self.bois.len() as u32),
-                    |mut tree, boi| {
-                        tree.add(boi.position.x, boi.position.y);
-                        tree
-                    },
-                )
-                .finish();
-
-            // Step 1) decision time
-            let decisions = self
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_length {
+            // Remember where everyone was so we can interpolate between ticks
+            self.previous = self
+                .world
                 .bois
                 .iter()
-                .map(|boi| boi.decide(self))
-                .collect::<Vec<_>>();
+                .map(|boi| (boi.id, (boi.position, boi.direction)))
+                .collect();
 
-            // Step 2) apply the decisions
-            self.bois
-                .iter_mut()
-                .zip(decisions)
-                .for_each(|(boi, new_direction)| {
-                    boi.action(1. / self.render.fps as f32, &new_direction);
-                });
-
-            // Step 3) Advance time
-            self.bois.iter_mut().for_each(|boi| {
-                boi.position = boi.position.add(
-                    &boi.direction_vector()
-                        .mul(boi.speed / self.render.fps as f32),
-                )
-            });
-
-            // Step 4) Apply consequences (Eg. bois being gobbled)
-            //Build a K-D tree of the bois
-            self.boi_tree = self
-                .bois
-                .iter()
-                .fold(
-                    KDTreeBuilder::new(self.bois.len() as u32),
-                    |mut tree, boi| {
-                        tree.add(boi.position.x, boi.position.y);
-                        tree
-                    },
-                )
-                .finish();
-
-            // Kill off any bois that got caught
-            let keep_bois = self
-                .bois
-                .iter()
-                .map(|boi| {
-                    // Predators always stay alive
-                    if boi.species == Species::Predator {
-                        return true;
-                    }
+            self.world.step(self.tick_length);
+            self.accumulator -= self.tick_length;
+            if let Some((recorder, _)) = &mut self.recorder {
+                recorder.record(&self.world);
+            }
+            if let Some(stats) = &mut self.stats {
+                stats.write(&TickStats::collect(&self.world))?;
+            }
 
-                    // For Prey, we check if there's any nearby predators
-                    let nearby_predator = self
-                        .boi_tree
-                        // Query the tree since it's quicker - kill range is 1 unit
-                        .within(boi.position.x, boi.position.y, 1.)
-                        .into_iter()
-                        // Get the bois based on the spatial query
-                        .map(|i| self.bois.get(i as usize).expect("Got invalid boi index!"))
-                        // Skip ourselves. todo: This is comparing that the entities are the same in memory,
-                        // this might bite me in the ass later. Probably better to do some unique entity IDs on
-                        // spawn instead.
-                        .filter(|boi2| !std::ptr::eq(*boi2, boi))
-                        // Check if there's any predators
-                        .any(|boi2| boi2.species == Species::Predator);
-
-                    !nearby_predator
-                })
-                .collect::<Vec<_>>();
+            ticks += 1;
+            if ticks == MAX_TICKS_PER_FRAME {
+                // Can't keep up, so just drop the time we're behind by
+                self.accumulator = 0.;
+                break;
+            }
+        }
 
-            // Kill em off
-            self.bois = std::mem::take(&mut self.bois)
-                .into_iter()
-                .zip(keep_bois)
-                .filter_map(|(boi, keep)| if keep { Some(boi) } else { None })
-                .collect();
+        Ok(())
+    }
 
-            self.render.needs_render = true;
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::F5) => {
+                match self.world.save(QUICKSAVE_PATH) {
+                    Ok(()) => println!("Saved to {}", QUICKSAVE_PATH),
+                    Err(e) => println!("{}", e),
+                }
+                return Ok(());
+            }
+            Some(KeyCode::F9) => {
+                // Loading rolls entity IDs back, so the replay would see the same ID twice
+                if self.recorder.is_some() {
+                    println!("Can't quickload while recording");
+                    return Ok(());
+                }
+                match World::load(QUICKSAVE_PATH) {
+                    Ok(world) => {
+                        println!("Loaded {}", QUICKSAVE_PATH);
+                        // The quicksave might be from a different scenario
+                        self.render =
+                            RenderState::new(ctx, &self.render_config, &Scenery::of(&world))?;
+                        self.world = world;
+                        self.tick_length = 1. / self.world.config.world.tick_rate as f32;
+                        self.accumulator = 0.;
+                        self.previous.clear();
+                        self.selected = None;
+                    }
+                    Err(e) => println!("{}", e),
+                }
+                return Ok(());
+            }
+            Some(KeyCode::M) => {
+                self.show_metrics = !self.show_metrics;
+                return Ok(());
+            }
+            Some(KeyCode::F) => {
+                self.show_flow = !self.show_flow;
+                return Ok(());
+            }
+            _ => {}
         }
 
+        // Fast forward & slow motion
+        let time_scale = match input.keycode {
+            Some(KeyCode::Equals) | Some(KeyCode::Plus) => self.time_scale * 2.,
+            Some(KeyCode::Minus) => self.time_scale / 2.,
+            Some(KeyCode::Key0) => 1.,
+            _ => return Ok(()),
+        };
+        self.time_scale = time_scale.clamp(1. / 64., 64.);
+        println!("Time scale: {}x", self.time_scale);
+
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        if self.render.needs_render {
-            println!("--- Draw ---");
-            let mut canvas = graphics::Canvas::from_frame(ctx, Color::from_rgb(128, 218, 235));
+        let mut canvas = graphics::Canvas::from_frame(ctx, Color::from_rgb(128, 218, 235));
+
+        let alpha = self.accumulator / self.tick_length;
+        let bois = self.world.bois.iter().map(|boi| {
+            let (position, direction) = self.interpolated(boi, alpha);
+
+            BoiSprite {
+                position,
+                direction,
+                colour: render::species_colour(self.world.species.get(boi.species)),
+                vision: boi.vision,
+                fov: boi.fov,
+            }
+        });
+        self.render
+            .draw_world(ctx, &mut canvas, self.world.food.as_ref(), bois)?;
+
+        // Debug - Who the selected boi can & can't see
+        if let Some(boi) = self
+            .selected
+            .and_then(|id| self.world.bois.iter().find(|boi| boi.id == id))
+        {
+            let vision = boi.vision
+                * self
+                    .world
+                    .biomes
+                    .modifiers(&boi.position, boi.species)
+                    .vision;
+            let lines = boi
+                .look(&self.world, vision)
+                .into_iter()
+                .map(|(other, _, hidden)| (self.interpolated(other, alpha).0, !hidden))
+                .collect::<Vec<_>>();
+            self.render.draw_sight_lines(
+                ctx,
+                &mut canvas,
+                &self.interpolated(boi, alpha).0,
+                &lines,
+            )?;
+        }
+
+        if self.show_flow && !self.world.flow.is_empty() {
+            let time = self.world.time();
+            self.render
+                .draw_flow(ctx, &mut canvas, |point| self.world.flow.at(point, time))?;
+        }
 
-            // Debug - Arena boundaries
-            let arena_pos = self.world_to_canvas(&self.arena_centre);
+        if self.show_metrics {
+            // One line per species that's still around
+            let hud = self
+                .world
+                .species
+                .iter()
+                .filter_map(|(id, species)| {
+                    let metrics = OrderParameters::measure(&self.world, Some(id))?;
+                    Some(format!(
+                        "{} ({}): polarization {:.2}, milling {:.2}, cohesion radius {:.1}, local alignment {:.2}",
+                        species.name,
+                        metrics.count,
+                        metrics.polarization,
+                        metrics.milling,
+                        metrics.cohesion_radius,
+                        metrics.local_alignment
+                    ))
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
             canvas.draw(
-                &self.render.assets.arena,
-                DrawParam::default()
-                    .dest([arena_pos.x, arena_pos.y])
-                    .scale([self.render.base_scale(), self.render.base_scale()]),
+                &Text::new(hud),
+                DrawParam::default().dest([10., 10.]).color(Color::BLACK),
             );
+        }
+
+        canvas.finish(ctx)
+    }
 
-            self.bois.iter().for_each(|boi| {
-                // Draw boi
-                let position = self.world_to_canvas(&boi.position);
-                let bbox = self.render.assets.boi.dimensions(ctx).unwrap().size();
-                canvas.draw(
-                    &self.render.assets.boi,
-                    DrawParam::default()
-                        .dest([position.x, position.y])
-                        // +PI/2 since our image is 90 degrees rotated left
-                        .rotation(boi.direction + PI / 2.)
-                        // Align image centre with Boi centre
-                        .offset([0.5, 0.5])
-                        // Handle scaling specifically for this image (see asset loading section)
-                        .scale([
-                            10. * self.render.screen_scale / bbox.x,
-                            10. * self.render.screen_scale / bbox.y,
-                        ])
-                        // Change the colour depending on the species
-                        .color(match boi.species {
-                            Species::Predator => Color::RED,
-                            Species::Prey => Color::GREEN,
-                        }),
-                );
-
-                // Debug - boi vision
-                canvas.draw(
-                    &self.render.assets.vision,
-                    DrawParam::default().dest([position.x, position.y]).scale([
-                        self.render.base_scale() * boi.vision,
-                        self.render.base_scale() * boi.vision,
-                    ]),
-                );
-            });
-
-            canvas.finish(ctx)?;
-
-            self.render.needs_render = false;
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        if button == MouseButton::Left {
+            // Select whoever's closest to the click, or nobody if it's nowhere near anyone
+            let point = self.render.canvas_to_world(&Vec2::new(x, y));
+            self.selected = self
+                .world
+                .boi_index
+                .nearest(&point, 1)
+                .first()
+                .filter(|n| n.distance <= SELECT_RADIUS)
+                .map(|n| self.world.bois[n.index].id);
         }
+
         Ok(())
     }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> GameResult<bool> {
+        if let Some((recorder, path)) = &self.recorder {
+            match recorder.save(path) {
+                Ok(()) => println!("Saved replay to {}", path.display()),
+                Err(e) => println!("{}", e),
+            }
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.flush()?;
+        }
+
+        // Carry on quitting
+        Ok(false)
+    }
 }
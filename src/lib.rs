@@ -0,0 +1,29 @@
+pub mod biome;
+pub mod boi;
+pub mod brain;
+pub mod breeding;
+pub mod config;
+pub mod entity;
+pub mod env;
+pub mod evolve;
+pub mod flow;
+pub mod food;
+pub mod game;
+pub mod metrics;
+pub mod nest;
+pub mod obstacle;
+pub mod par;
+pub mod perception;
+pub mod playback;
+pub mod render;
+pub mod replay;
+pub mod shape;
+pub mod sight;
+pub mod snapshot;
+pub mod spatial;
+pub mod species;
+pub mod stats;
+pub mod steering;
+pub mod strategy;
+pub mod vec;
+pub mod world;
@@ -1,27 +1,185 @@
-use game::MainState;
-use ggez::{event, GameResult};
+use std::path::{Path, PathBuf};
 
-mod boi;
-mod entity;
-mod game;
-mod nest;
-mod render;
-mod strategy;
-mod vec;
+use boids::{
+    brain::Genome,
+    config::Config,
+    evolve::Evolution,
+    game::MainState,
+    playback::ReplayState,
+    replay::{Recorder, Replay},
+    stats::{StatsWriter, TickStats},
+    world::World,
+};
+use clap::Parser;
+use ggez::{
+    event::{self, EventLoop},
+    Context, GameError, GameResult,
+};
+
+#[derive(Parser)]
+#[command(about = "Simple swarm simulation using the Boids model")]
+struct Args {
+    /// Scenario file (TOML). Defaults are used for anything not in the file.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Master seed for the simulation. Overrides the scenario's seed, picked at random if neither
+    /// is given. Can't be used with --load, as the snapshot carries on with its own.
+    #[arg(long, conflicts_with = "load")]
+    seed: Option<u64>,
+
+    /// Run the simulation without a window
+    #[arg(long)]
+    headless: bool,
+
+    /// Number of ticks to run for in headless mode
+    #[arg(long, default_value_t = 1000)]
+    ticks: u32,
+
+    /// Carry on from a saved snapshot instead of starting a new world. The scenario is taken from
+    /// the snapshot, so --config only affects rendering.
+    #[arg(long)]
+    load: Option<PathBuf>,
+
+    /// Save a snapshot at the end of a headless run
+    #[arg(long)]
+    save: Option<PathBuf>,
+
+    /// Record the run to a replay file. Headless runs write it at the end, otherwise it's written
+    /// when the window is closed.
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Write per-tick population & trait stats to a file. Uses JSON Lines if the file ends in
+    /// .jsonl, otherwise CSV.
+    #[arg(long)]
+    stats: Option<PathBuf>,
+
+    /// Play back a replay file instead of running a simulation
+    #[arg(long, conflicts_with_all = ["load", "headless", "record", "stats"])]
+    replay: Option<PathBuf>,
+
+    /// Evolve a brain for the species in the scenario's [evolution] section, saving the fittest
+    /// genomes to this file after every generation
+    #[arg(long, conflicts_with_all = ["load", "replay", "record", "stats", "save"])]
+    evolve: Option<PathBuf>,
+}
 
 pub fn main() -> GameResult {
-    let screen_scale = 3.; // How much bigger is the rendering than the world
-    let padding = 100.; // 100 pixels padding on each side of the arena
-    let arena_radius = 100.; // World units
-    let fps = 30;
+    let args = Args::parse();
 
-    let (mut ctx, event_loop) = ggez::ContextBuilder::new("bois", "adam")
-        .window_setup(ggez::conf::WindowSetup::default().title("Bois"))
-        .window_mode(ggez::conf::WindowMode::default().dimensions(
-            arena_radius * 2. * screen_scale + padding * 2.,
-            arena_radius * 2. * screen_scale + padding * 2.,
-        ))
-        .build()?;
-    let state = MainState::new(&mut ctx, arena_radius, 1000, screen_scale, fps, padding)?;
+    let config = match &args.config {
+        Some(path) => Config::load(path),
+        None => Ok(Config::default()),
+    }
+    .map_err(|e| GameError::CustomError(e.to_string()))?;
+
+    if let Some(path) = &args.replay {
+        let replay = Replay::load(path).map_err(|e| GameError::CustomError(e.to_string()))?;
+        println!("Playing {} ({} ticks)", path.display(), replay.frames.len());
+
+        let (mut ctx, event_loop) = build_window(&config, replay.scenery.arena_radius)?;
+        let state = ReplayState::new(&mut ctx, &config, replay)?;
+        event::run(ctx, event_loop, state)
+    }
+
+    if let Some(path) = &args.evolve {
+        return evolve(&config, args.seed, path);
+    }
+
+    let mut world = match &args.load {
+        Some(path) => {
+            let world = World::load(path).map_err(|e| GameError::CustomError(e.to_string()))?;
+            println!("Loaded {} at tick {}", path.display(), world.tick);
+            world
+        }
+        None => {
+            // Print out the seed so interesting runs can be reproduced
+            let seed = args.seed.or(config.world.seed).unwrap_or_else(rand::random);
+            println!("Seed: {}", seed);
+            World::new(&config, seed)
+        }
+    };
+
+    let stats = args
+        .stats
+        .as_ref()
+        .map(StatsWriter::create)
+        .transpose()
+        .map_err(|e| GameError::CustomError(format!("Couldn't create stats file: {}", e)))?;
+
+    if args.headless {
+        let mut recorder = args.record.as_ref().map(|_| Recorder::new(&world));
+        let mut stats = stats;
+        for _ in 0..args.ticks {
+            world.step(1. / world.config.world.tick_rate as f32);
+            if let Some(recorder) = &mut recorder {
+                recorder.record(&world);
+            }
+            if let Some(stats) = &mut stats {
+                stats.write(&TickStats::collect(&world))?;
+            }
+        }
+        if let Some(stats) = &mut stats {
+            stats.flush()?;
+        }
+        println!("Bois left after {} ticks: {}", args.ticks, world.bois.len());
+
+        if let Some(path) = &args.save {
+            world
+                .save(path)
+                .map_err(|e| GameError::CustomError(e.to_string()))?;
+            println!("Saved {} at tick {}", path.display(), world.tick);
+        }
+        if let (Some(recorder), Some(path)) = (&recorder, &args.record) {
+            recorder
+                .save(path)
+                .map_err(|e| GameError::CustomError(e.to_string()))?;
+            println!("Saved replay to {}", path.display());
+        }
+        return Ok(());
+    }
+
+    let (mut ctx, event_loop) = build_window(&config, world.arena_radius)?;
+    let mut state = MainState::new(&mut ctx, &config, world)?;
+    if let Some(path) = args.record {
+        state.record_to(path);
+    }
+    if let Some(stats) = stats {
+        state.write_stats_to(stats);
+    }
     event::run(ctx, event_loop, state)
 }
+
+fn evolve(config: &Config, seed: Option<u64>, path: &Path) -> GameResult {
+    let Some(settings) = &config.evolution else {
+        return Err(GameError::CustomError(
+            "Scenario needs an [evolution] section to evolve".into(),
+        ));
+    };
+    let seed = seed.or(config.world.seed).unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+
+    let mut evolution =
+        Evolution::new(config, seed).map_err(|e| GameError::CustomError(e.to_string()))?;
+    // Something to beat, scored on the first generation's episodes
+    println!("Following the rules: fitness {:.3}", evolution.baseline());
+    for _ in 0..settings.generations {
+        let elites = evolution.step();
+        println!(
+            "Generation {}: best fitness {:.3}",
+            evolution.generation, elites[0].fitness
+        );
+        Genome::save(path, &elites).map_err(|e| GameError::CustomError(e.to_string()))?;
+    }
+    println!("Saved the fittest genomes to {}", path.display());
+    Ok(())
+}
+
+fn build_window(config: &Config, arena_radius: f32) -> GameResult<(Context, EventLoop<()>)> {
+    let window_size = arena_radius * 2. * config.render.screen_scale + config.render.padding * 2.;
+    ggez::ContextBuilder::new("bois", "adam")
+        .window_setup(ggez::conf::WindowSetup::default().title("Bois"))
+        .window_mode(ggez::conf::WindowMode::default().dimensions(window_size, window_size))
+        .build()
+}
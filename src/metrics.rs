@@ -0,0 +1,213 @@
+//! Standard collective motion order parameters, for telling apart the different regimes a flock
+//! can settle into:
+//! - Schooling: high polarization, low milling
+//! - Milling: low polarization, high milling
+//! - Swarming: both low, but still cohesive
+
+use crate::{boi::Boi, perception::Perception, species::SpeciesId, vec::Vec2, world::World};
+
+/// Snapshot of how a group of bois is moving
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderParameters {
+    pub count: usize,
+    pub polarization: f32, // 0 (all over the place) to 1 (all facing the same way)
+    pub milling: f32,      // 0 (no rotation) to 1 (everyone circling the centre)
+    pub cohesion_radius: f32, // World units
+    pub local_alignment: f32, // 0 to 1, like polarization but only between bois that can see each other
+}
+
+impl OrderParameters {
+    /// Measure everyone in the world, or just one species. None if there's nobody to measure.
+    pub fn measure(world: &World, species: Option<SpeciesId>) -> Option<Self> {
+        let bois = world
+            .bois
+            .iter()
+            .filter(|boi| species.is_none_or(|s| boi.species == s))
+            .collect::<Vec<_>>();
+        if bois.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            count: bois.len(),
+            polarization: polarization(&bois),
+            milling: milling(&bois),
+            cohesion_radius: cohesion_radius(&bois),
+            local_alignment: density_weighted_alignment(world, species),
+        })
+    }
+}
+
+/// Mean position of the group
+pub fn centre(bois: &[&Boi]) -> Vec2 {
+    mean(bois.iter().map(|boi| boi.position))
+}
+
+/// Length of the mean heading
+pub fn polarization(bois: &[&Boi]) -> f32 {
+    length(&mean(bois.iter().map(|boi| boi.direction_vector())))
+}
+
+/// Normalised angular momentum about the group's centre. Comes out the same whichever way round
+/// the group is circling.
+pub fn milling(bois: &[&Boi]) -> f32 {
+    let centre = centre(bois);
+    let momentum = mean_scalar(bois.iter().map(|boi| {
+        let offset = boi.position.sub(&centre).normalise();
+        let heading = boi.direction_vector();
+        // 2D cross product
+        offset.x * heading.y - offset.y * heading.x
+    }));
+
+    momentum.abs()
+}
+
+/// Root mean square distance from the group's centre (AKA radius of gyration)
+pub fn cohesion_radius(bois: &[&Boi]) -> f32 {
+    let centre = centre(bois);
+    mean_scalar(
+        bois.iter()
+            .map(|boi| boi.position.distance(&centre).powi(2)),
+    )
+    .sqrt()
+}
+
+/// How well each boi lines up with the neighbours it can see, weighted by how many neighbours it
+/// has, so lone stragglers don't count for much. Only neighbours of the same group count. Bois
+/// see the same neighbours here as they steer by, so biomes, blind spots & cover all count.
+pub fn density_weighted_alignment(world: &World, species: Option<SpeciesId>) -> f32 {
+    let in_group = |species_id: SpeciesId| species.is_none_or(|s| species_id == s);
+
+    let (total, weights) = world
+        .bois
+        .iter()
+        .filter(|boi| in_group(boi.species))
+        .map(|boi| {
+            let perception = Perception::of(world, boi);
+            let neighbours = perception
+                .neighbours
+                .iter()
+                .filter(|neighbour| in_group(neighbour.species))
+                .map(|neighbour| Vec2::new(neighbour.heading.cos(), neighbour.heading.sin()))
+                .collect::<Vec<_>>();
+
+            // Alignment of the boi & everyone it can see
+            let local = length(&mean(
+                neighbours
+                    .iter()
+                    .copied()
+                    .chain([perception.direction_vector()]),
+            ));
+            let weight = neighbours.len() as f32;
+            (local * weight, weight)
+        })
+        .fold((0., 0.), |(total, weights), (value, weight)| {
+            (total + value, weights + weight)
+        });
+
+    if weights == 0. {
+        // Nobody can see anybody
+        0.
+    } else {
+        total / weights
+    }
+}
+
+fn mean(vecs: impl Iterator<Item = Vec2>) -> Vec2 {
+    let (sum, count) = vecs.fold((Vec2::new(0., 0.), 0), |(sum, count), vec| {
+        (sum.add(&vec), count + 1)
+    });
+
+    if count == 0 {
+        sum
+    } else {
+        sum.div(count as f32)
+    }
+}
+
+fn mean_scalar(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0., 0), |(sum, count), value| (sum + value, count + 1));
+
+    if count == 0 {
+        0.
+    } else {
+        sum / count as f32
+    }
+}
+
+fn length(vec: &Vec2) -> f32 {
+    vec.distance(&Vec2::new(0., 0.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::f32::consts::PI;
+
+    /// Bois evenly spaced around a circle, facing `offset` radians anticlockwise of outwards
+    fn ring(n: usize, radius: f32, offset: f32) -> Vec<Boi> {
+        (0..n)
+            .map(|i| {
+                let angle = i as f32 / n as f32 * 2. * PI;
+                Boi::test_at(
+                    i as u64,
+                    0,
+                    Vec2::new(angle.cos() * radius, angle.sin() * radius),
+                    angle + offset,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn schooling() {
+        let mut bois = ring(12, 5., 0.);
+        bois.iter_mut().for_each(|boi| boi.direction = 1.);
+        let bois = bois.iter().collect::<Vec<_>>();
+
+        assert!((polarization(&bois) - 1.).abs() < 1e-5);
+        assert!(milling(&bois) < 0.5);
+        assert!((cohesion_radius(&bois) - 5.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn only_counts_who_can_be_seen() {
+        let mut config = Config::default();
+        config.world.num_bois = 0;
+        let mut world = World::new(&config, 1);
+        // A line along x, where the middle one can't see the one behind it, facing the other way
+        world.bois = vec![
+            Boi {
+                fov: PI,
+                ..Boi::test_at(0, 0, Vec2::new(0., 0.), 0.)
+            },
+            Boi::test_at(1, 0, Vec2::new(-3., 0.), PI),
+            Boi::test_at(2, 0, Vec2::new(3., 0.), 0.),
+        ];
+        world.boi_index = config
+            .world
+            .spatial_index
+            .build(&world.bois.iter().map(|b| b.position).collect::<Vec<_>>());
+
+        // 1 for the middle one seeing the one in front, & 1/3 twice over for the others seeing
+        // both of theirs
+        let alignment = density_weighted_alignment(&world, Some(0));
+        assert!(
+            (alignment - (1. + 2. / 3. * 2.) / 5.).abs() < 1e-5,
+            "{}",
+            alignment
+        );
+    }
+
+    #[test]
+    fn milling_either_way() {
+        for offset in [PI / 2., -PI / 2.] {
+            let bois = ring(12, 5., offset);
+            let bois = bois.iter().collect::<Vec<_>>();
+
+            assert!(polarization(&bois) < 1e-5);
+            assert!((milling(&bois) - 1.).abs() < 1e-5);
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{entity::EntityTemplate, vec::Vec2};
+use crate::{
+    entity::{EntityId, EntityTemplate},
+    vec::Vec2,
+};
 use rand::{prelude::Distribution, Rng};
 
 /// A Nest is some spawning point for an Entity.
@@ -7,17 +10,31 @@ pub struct Nest<R: Rng, D: Distribution<f32>, T: EntityTemplate> {
     pub pos: D,
     pub direction: D,
     pub template: T,
+    pub next_id: EntityId, // ID given to the next spawned entity
 }
 
 impl<R: Rng, D: Distribution<f32>, T: EntityTemplate> Nest<R, D, T> {
     /// Spawn a new Entity near the nest
     pub fn spawn(&mut self) -> T::Entity {
-        let position = Vec2 {
+        let position = self.position();
+        let direction = self.direction.sample(&mut self.rng);
+
+        let id = self.new_id();
+        self.template.spawn(&mut self.rng, id, &position, direction)
+    }
+
+    /// Somewhere near the nest
+    pub fn position(&mut self) -> Vec2 {
+        Vec2 {
             x: self.pos.sample(&mut self.rng),
             y: self.pos.sample(&mut self.rng),
-        };
-        let direction = self.direction.sample(&mut self.rng);
+        }
+    }
 
-        self.template.spawn(&mut self.rng, &position, direction)
+    /// Hand out an ID, for entities that weren't spawned by the nest itself
+    pub fn new_id(&mut self) -> EntityId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
     }
 }
@@ -0,0 +1,198 @@
+use crate::{
+    shape::{Hit, Shape, ShapeConfig, ShapeIndex},
+    vec::Vec2,
+};
+
+/// How far outside an obstacle a boi gets put if it ends up inside one
+const SKIN: f32 = 0.01;
+
+/// Give up pushing a boi out of obstacles after this many goes. Only matters if obstacles overlap
+/// enough that pushing out of one lands inside another.
+const MAX_PUSHES: usize = 8;
+
+/// All the obstacles in the world, indexed spatially so bois only have to check the ones near them
+pub struct Obstacles {
+    index: ShapeIndex,
+}
+
+impl Obstacles {
+    pub fn new(shapes: Vec<Shape>) -> Self {
+        Self {
+            index: ShapeIndex::new(shapes),
+        }
+    }
+
+    pub fn from_config(configs: &[ShapeConfig]) -> Self {
+        Self::new(configs.iter().map(Shape::from).collect())
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        self.index.shapes()
+    }
+
+    pub fn contains(&self, point: &Vec2) -> bool {
+        self.index.containing(point).next().is_some()
+    }
+
+    /// Move a point that's ended up inside an obstacle to just outside it. None if it's still
+    /// inside one after a few goes, so the caller can put it somewhere else.
+    pub fn push_out(&self, point: &Vec2) -> Option<Vec2> {
+        let mut point = *point;
+        for _ in 0..MAX_PUSHES {
+            let Some((_, shape)) = self.index.containing(&point).next() else {
+                return Some(point);
+            };
+            let (edge, out) = shape.way_out(&point);
+            point = edge.add(&out.mul(SKIN));
+        }
+        (!self.contains(&point)).then_some(point)
+    }
+
+    /// First obstacle a ray hits within `length`
+    pub fn raycast(&self, origin: &Vec2, direction: &Vec2, length: f32) -> Option<Hit> {
+        let end = origin.add(&direction.mul(length));
+        let min = Vec2::new(origin.x.min(end.x), origin.y.min(end.y));
+        let max = Vec2::new(origin.x.max(end.x), origin.y.max(end.y));
+
+        self.index
+            .near(&min, &max)
+            .filter_map(|(_, shape)| shape.raycast(origin, direction, length))
+            .min_by(|h1, h2| h1.distance.total_cmp(&h2.distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A U shape, open at the top
+    fn cup() -> Shape {
+        Shape::Polygon {
+            points: [
+                (0., 0.),
+                (6., 0.),
+                (6., 6.),
+                (4., 6.),
+                (4., 2.),
+                (2., 2.),
+                (2., 6.),
+                (0., 6.),
+            ]
+            .into_iter()
+            .map(|(x, y)| Vec2::new(x, y))
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn pushed_out_nearest_side() {
+        // Whichever way round the points go
+        let cup = cup();
+        let obstacles = Obstacles::new(vec![cup.clone()]);
+        assert_eq!(
+            obstacles.push_out(&Vec2::new(1.9, 4.)).unwrap().x,
+            2. + SKIN
+        );
+        let Shape::Polygon { mut points } = cup else {
+            unreachable!()
+        };
+        points.reverse();
+        let obstacles = Obstacles::new(vec![Shape::Polygon { points }]);
+        assert_eq!(
+            obstacles.push_out(&Vec2::new(1.9, 4.)).unwrap().x,
+            2. + SKIN
+        );
+    }
+
+    #[test]
+    fn stuck_between_overlapping_shapes() {
+        let rectangle = |bottom: f32, top: f32| Shape::Polygon {
+            points: [(0., bottom), (10., bottom), (10., top), (0., top)]
+                .into_iter()
+                .map(|(x, y)| Vec2::new(x, y))
+                .collect(),
+        };
+        // Getting out of either one lands inside the other, back & forth forever
+        let obstacles = Obstacles::new(vec![rectangle(0., 2.), rectangle(1.5, 3.5)]);
+        assert_eq!(obstacles.push_out(&Vec2::new(5., 1.9)), None);
+
+        assert_eq!(
+            obstacles.push_out(&Vec2::new(5., 0.2)),
+            Some(Vec2::new(5., -SKIN))
+        );
+        assert_eq!(
+            obstacles.push_out(&Vec2::new(5., 5.)),
+            Some(Vec2::new(5., 5.))
+        );
+    }
+
+    #[test]
+    fn nobody_gets_inside() {
+        let mut config = crate::config::Config::default();
+        config.world.arena_radius = 30.;
+        config.world.num_bois = 300;
+        config.obstacles = vec![
+            ShapeConfig::Circle {
+                centre: [10., 0.],
+                radius: 8.,
+            },
+            ShapeConfig::Polygon {
+                points: vec![
+                    [-20., -5.],
+                    [0., -5.],
+                    [0., 5.],
+                    [-5., 5.],
+                    [-5., 0.],
+                    [-20., 0.],
+                ],
+            },
+            // Overlapping enough to get stuck in
+            ShapeConfig::Polygon {
+                points: vec![[-10., 10.], [10., 10.], [10., 14.], [-10., 14.]],
+            },
+            ShapeConfig::Polygon {
+                points: vec![[-10., 13.], [10., 13.], [10., 17.], [-10., 17.]],
+            },
+        ];
+
+        let mut world = crate::world::World::new(&config, 9);
+        for _ in 0..100 {
+            assert!(world
+                .bois
+                .iter()
+                .all(|boi| !world.obstacles.contains(&boi.position)));
+            world.step(1. / 30.);
+        }
+    }
+
+    #[test]
+    fn raycasts() {
+        let obstacles = Obstacles::new(vec![
+            cup(),
+            Shape::Circle {
+                centre: Vec2::new(20., 1.),
+                radius: 2.,
+            },
+        ]);
+
+        // Down into the cup
+        let hit = obstacles
+            .raycast(&Vec2::new(3., 10.), &Vec2::new(0., -1.), 20.)
+            .unwrap();
+        assert!((hit.distance - 8.).abs() < 1e-5);
+        assert_eq!(hit.normal, Vec2::new(0., 1.));
+
+        // Across to the circle
+        let hit = obstacles
+            .raycast(&Vec2::new(10., 1.), &Vec2::new(1., 0.), 20.)
+            .unwrap();
+        assert!((hit.distance - 8.).abs() < 1e-5);
+        assert_eq!(hit.normal, Vec2::new(-1., 0.));
+
+        // Too short to reach
+        assert_eq!(
+            obstacles.raycast(&Vec2::new(10., 1.), &Vec2::new(1., 0.), 5.),
+            None
+        );
+    }
+}
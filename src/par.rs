@@ -0,0 +1,36 @@
+//! Helpers for running per-boi work across all CPU cores when the `parallel` feature is on.
+//! Results always come out in the same order as the input, so parallel & serial runs are
+//! identical for a given seed.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Map over a slice, in parallel if the `parallel` feature is on
+#[cfg(feature = "parallel")]
+pub fn map<T, U, F>(items: &[T], f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> U + Sync + Send,
+{
+    items.par_iter().map(f).collect()
+}
+
+/// Map over a slice, in parallel if the `parallel` feature is on
+#[cfg(not(feature = "parallel"))]
+pub fn map<T, U, F>(items: &[T], f: F) -> Vec<U>
+where
+    F: Fn(&T) -> U,
+{
+    items.iter().map(f).collect()
+}
+
+/// Filter & map over a slice, in parallel if the `parallel` feature is on
+pub fn filter_map<T, U, F>(items: &[T], f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T) -> Option<U> + Sync + Send,
+{
+    map(items, f).into_iter().flatten().collect()
+}
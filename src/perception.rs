@@ -0,0 +1,165 @@
+//! Everything a single boi can make out about the world around it. Strategies only ever get to
+//! see this, so they can't cheat by peeking at the whole world, and can be tested with made up
+//! perceptions.
+
+use crate::{
+    boi::{Boi, Weights},
+    shape::Hit,
+    species::SpeciesId,
+    vec::Vec2,
+    world::World,
+};
+
+/// Another boi, as seen by the one perceiving it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbour {
+    pub offset: Vec2,   // Where it is, relative to us
+    pub position: Vec2, // Where it is in the world
+    pub distance: f32,
+    pub heading: f32, // Which way it's facing, radians
+    pub species: SpeciesId,
+    pub prey: bool,     // We can eat it
+    pub predator: bool, // It can eat us
+}
+
+/// Where the edges of the world are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Boundary {
+    pub centre: Vec2,  // Offset from us to the middle of the arena
+    pub distance: f32, // How far away the middle is
+    pub radius: f32,
+    pub obstacle: Option<Hit>, // First obstacle straight ahead, if there's one within our vision
+}
+
+impl Boundary {
+    /// How far in from the edge of the arena we are, negative once we're outside it
+    pub fn edge_distance(&self) -> f32 {
+        self.radius - self.distance
+    }
+}
+
+/// What the surroundings are like right where we are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Environment {
+    pub flow: Vec2,         // Wind or current carrying us along
+    pub food: Option<Vec2>, // Which way the food is, for grazers. See [crate::food::Food::scent].
+}
+
+/// What a boi knows about itself & everything it can see
+#[derive(Debug, Clone, PartialEq)]
+pub struct Perception {
+    pub species: SpeciesId,
+    pub position: Vec2,             // Where we are in the world
+    pub heading: f32,               // Which way we're facing, radians
+    pub speed: f32,                 // How fast we can go here
+    pub vision: f32,                // How far we can see here
+    pub weights: Weights,           // How much we care about each rule
+    pub neighbours: Vec<Neighbour>, // Everyone we can see, in no particular order
+    pub boundary: Boundary,
+    pub environment: Environment,
+}
+
+impl Perception {
+    /// Take a look around from where `boi` is
+    pub fn of(world: &World, boi: &Boi) -> Self {
+        // How far we can see (& how fast we can go) depends on where we are
+        let modifiers = world.biomes.modifiers(&boi.position, boi.species);
+        let vision = boi.vision * modifiers.vision;
+
+        let neighbours = boi
+            .look(world, vision)
+            .into_iter()
+            .filter(|(_, _, hidden)| !hidden)
+            .map(|(other, distance, _)| Neighbour {
+                offset: other.position.sub(&boi.position),
+                position: other.position,
+                distance,
+                heading: other.direction,
+                species: other.species,
+                prey: world.species.eats(boi.species, other.species),
+                predator: world.species.eats(other.species, boi.species),
+            })
+            .collect();
+
+        Self {
+            species: boi.species,
+            position: boi.position,
+            heading: boi.direction,
+            speed: boi.speed * modifiers.speed,
+            vision,
+            weights: boi.weights,
+            neighbours,
+            boundary: Boundary {
+                centre: world.arena_centre.sub(&boi.position),
+                distance: world.arena_centre.distance(&boi.position),
+                radius: world.arena_radius,
+                obstacle: world
+                    .obstacles
+                    .raycast(&boi.position, &boi.direction_vector(), vision),
+            },
+            environment: Environment {
+                flow: world.flow.at(&boi.position, world.time()),
+                food: world
+                    .food
+                    .as_ref()
+                    .filter(|food| food.grazes(boi.species))
+                    .and_then(|food| food.scent(&boi.position, vision)),
+            },
+        }
+    }
+
+    /// Unit vector the way we're facing
+    pub fn direction_vector(&self) -> Vec2 {
+        Vec2::new(self.heading.cos(), self.heading.sin())
+    }
+
+    /// Neighbours of our own species
+    pub fn friends(&self) -> impl Iterator<Item = &Neighbour> {
+        self.neighbours
+            .iter()
+            .filter(move |neighbour| neighbour.species == self.species)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn boi(id: u64, species: SpeciesId, x: f32, y: f32) -> Boi {
+        Boi::test_at(id, species, Vec2::new(x, y), 1.)
+    }
+
+    #[test]
+    fn everything_is_relative() {
+        let mut config = Config::default();
+        config.world.num_bois = 0;
+        let mut world = World::new(&config, 1);
+        world.bois = vec![boi(0, 1, 90., 0.), boi(1, 0, 93., 4.), boi(2, 1, 50., 0.)];
+        world.boi_index = config.world.spatial_index.build(
+            &world
+                .bois
+                .iter()
+                .map(|boi| boi.position)
+                .collect::<Vec<_>>(),
+        );
+
+        // Only the predator's close enough to see, & it can eat us
+        let perception = Perception::of(&world, &world.bois[0]);
+        assert_eq!(
+            perception.neighbours,
+            vec![Neighbour {
+                offset: Vec2::new(3., 4.),
+                position: Vec2::new(93., 4.),
+                distance: 5.,
+                heading: 1.,
+                species: 0,
+                prey: false,
+                predator: true,
+            }]
+        );
+        assert_eq!(perception.boundary.centre, Vec2::new(-90., 0.));
+        assert_eq!(perception.boundary.edge_distance(), 10.);
+        assert_eq!(perception.environment.food, None);
+    }
+}
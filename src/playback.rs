@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use ggez::{
+    event::{EventHandler, MouseButton},
+    graphics::{self, Color, DrawMode, DrawParam, Mesh, Rect, Text},
+    input::keyboard::{KeyCode, KeyInput},
+    Context, GameResult,
+};
+
+use crate::{
+    config::Config,
+    entity::EntityId,
+    render::{self, BoiSprite, RenderState},
+    replay::{Replay, Spawn},
+};
+
+/// Height of the timeline bar in pixels
+const TIMELINE_HEIGHT: f32 = 10.;
+
+/// Plays back a [Replay], drawing it the same way a live run gets drawn
+pub struct ReplayState {
+    replay: Replay,
+    spawns: HashMap<EntityId, Spawn>, // Species, vision & field of view of every boi in the replay
+    frame: usize,                     // Frame being shown
+    tick_length: f32,                 // Seconds between frames at normal speed
+    time_scale: f32,
+    accumulator: f32, // Time since the current frame, for interpolating to the next
+    paused: bool,
+    scrubbing: bool, // Mouse is held down on the timeline
+
+    render: RenderState,
+}
+
+impl ReplayState {
+    pub fn new(ctx: &mut Context, config: &Config, replay: Replay) -> GameResult<Self> {
+        let render = RenderState::new(ctx, &config.render, &replay.scenery)?;
+
+        Ok(Self {
+            spawns: replay.spawns(),
+            tick_length: 1. / replay.tick_rate as f32,
+            replay,
+            frame: 0,
+            time_scale: config.render.time_scale,
+            accumulator: 0.,
+            paused: false,
+            scrubbing: false,
+            render,
+        })
+    }
+
+    fn last_frame(&self) -> usize {
+        self.replay.frames.len().saturating_sub(1)
+    }
+
+    fn jump_to(&mut self, frame: usize) {
+        self.frame = frame.min(self.last_frame());
+        self.accumulator = 0.;
+    }
+
+    /// Where the timeline bar goes, along the bottom of the window
+    fn timeline(&self, ctx: &Context) -> Rect {
+        let (width, height) = ctx.gfx.drawable_size();
+        let padding = self.render.padding;
+        Rect::new(
+            padding,
+            height - (padding + TIMELINE_HEIGHT) / 2.,
+            width - padding * 2.,
+            TIMELINE_HEIGHT,
+        )
+    }
+
+    /// Jump to wherever on the timeline the mouse is
+    fn scrub(&mut self, ctx: &Context, x: f32) {
+        let timeline = self.timeline(ctx);
+        let fraction = ((x - timeline.x) / timeline.w).clamp(0., 1.);
+        self.jump_to((fraction * self.last_frame() as f32).round() as usize);
+    }
+}
+
+impl EventHandler for ReplayState {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.paused || self.scrubbing {
+            return Ok(());
+        }
+
+        self.accumulator += ctx.time.delta().as_secs_f32() * self.time_scale;
+        while self.accumulator >= self.tick_length {
+            if self.frame == self.last_frame() {
+                // Hold on the last frame
+                self.accumulator = 0.;
+                self.paused = true;
+                break;
+            }
+            self.frame += 1;
+            self.accumulator -= self.tick_length;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::Space) => {
+                if self.paused && self.frame == self.last_frame() {
+                    // Start again from the top
+                    self.jump_to(0);
+                }
+                self.paused = !self.paused;
+            }
+            // Frame stepping pauses so you can see what's going on
+            Some(KeyCode::Right) => {
+                self.paused = true;
+                self.jump_to(self.frame + 1);
+            }
+            Some(KeyCode::Left) => {
+                self.paused = true;
+                self.jump_to(self.frame.saturating_sub(1));
+            }
+            Some(KeyCode::Home) => self.jump_to(0),
+            Some(KeyCode::End) => self.jump_to(self.last_frame()),
+            Some(KeyCode::Equals) | Some(KeyCode::Plus) => {
+                self.time_scale = (self.time_scale * 2.).min(64.)
+            }
+            Some(KeyCode::Minus) => self.time_scale = (self.time_scale / 2.).max(1. / 64.),
+            Some(KeyCode::Key0) => self.time_scale = 1.,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        // Be a bit generous with where counts as the timeline
+        let timeline = self.timeline(ctx);
+        if button == MouseButton::Left && (y - timeline.center().y).abs() <= TIMELINE_HEIGHT * 2. {
+            self.scrubbing = true;
+            self.scrub(ctx, x);
+        }
+
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> GameResult {
+        if button == MouseButton::Left {
+            self.scrubbing = false;
+        }
+
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        _y: f32,
+        _dx: f32,
+        _dy: f32,
+    ) -> GameResult {
+        if self.scrubbing {
+            self.scrub(ctx, x);
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = graphics::Canvas::from_frame(ctx, Color::from_rgb(128, 218, 235));
+
+        let Some(current) = self.replay.frames.get(self.frame) else {
+            // Nothing was recorded
+            return canvas.finish(ctx);
+        };
+
+        // Head towards wherever everyone is on the next frame
+        let alpha = self.accumulator / self.tick_length;
+        let next = self
+            .replay
+            .frames
+            .get(self.frame + 1)
+            .map(|frame| {
+                frame
+                    .bois
+                    .iter()
+                    .map(|boi| (boi.id, (boi.position, boi.direction)))
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+
+        let bois = current.bois.iter().map(|boi| {
+            let current = (boi.position, boi.direction);
+            let (position, direction) = match next.get(&boi.id) {
+                Some(next) => render::interpolate(current, *next, alpha),
+                // About to get gobbled
+                None => current,
+            };
+            let spawn = &self.spawns[&boi.id];
+
+            BoiSprite {
+                position,
+                direction,
+                colour: render::species_colour(&self.replay.species[spawn.species]),
+                vision: spawn.vision,
+                fov: spawn.fov,
+            }
+        });
+        self.render.draw_world(ctx, &mut canvas, None, bois)?;
+
+        // Timeline, with a marker for how far through we are
+        let timeline = self.timeline(ctx);
+        let progress = self.frame as f32 / self.last_frame().max(1) as f32;
+        let bar =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), timeline, Color::new(0., 0., 0., 0.3))?;
+        let played = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(
+                timeline.x,
+                timeline.y,
+                (timeline.w * progress).max(1.),
+                timeline.h,
+            ),
+            Color::BLACK,
+        )?;
+        canvas.draw(&bar, DrawParam::default());
+        canvas.draw(&played, DrawParam::default());
+
+        let status = format!(
+            "Tick {} ({}/{}) {}x{}",
+            current.tick,
+            self.frame,
+            self.last_frame(),
+            self.time_scale,
+            if self.paused { " - paused" } else { "" }
+        );
+        canvas.draw(
+            &Text::new(status),
+            DrawParam::default()
+                .dest([timeline.x, timeline.y - 20.])
+                .color(Color::BLACK),
+        );
+
+        canvas.finish(ctx)
+    }
+}
@@ -1,22 +1,60 @@
+use std::f32::consts::{PI, TAU};
+
 use ggez::{
-    graphics::{Color, DrawMode, Image, Mesh},
+    graphics::{Canvas, Color, DrawMode, DrawParam, Drawable, Image, Mesh, MeshBuilder, Rect},
     Context, GameResult,
 };
 
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::RenderConfig, food::Food, shape::Shape, species::Species, vec::Vec2, world::World,
+};
+
+/// World units between the arrows of the flow overlay
+const FLOW_ARROW_SPACING: f32 = 10.;
+
+/// The parts of the world that never change, which is all the assets need to know about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenery {
+    pub arena_centre: Vec2,
+    pub arena_radius: f32,
+    pub obstacles: Vec<Shape>,
+    pub biomes: Vec<(Shape, [u8; 3])>, // Region & colour of each biome, drawn in order
+}
+
+impl Scenery {
+    pub fn of(world: &World) -> Self {
+        Self {
+            arena_centre: world.arena_centre,
+            arena_radius: world.arena_radius,
+            obstacles: world.obstacles.shapes().to_vec(),
+            biomes: world
+                .biomes
+                .iter()
+                .map(|(biome, region)| (region.clone(), biome.colour))
+                .collect(),
+        }
+    }
+}
+
 /// Handles to all our loaded assets, loaded up once and re-used
 pub struct Assets {
     pub arena: Mesh,
     pub boi: Image,
     pub vision: Mesh,
+    // Meshes with all the shapes in one go, positioned relative to the origin
+    pub obstacles: Option<Mesh>,
+    pub biomes: Option<Mesh>,
 }
 
 impl Assets {
-    pub fn load(ctx: &mut Context, mesh_raster_scale: f32, arena_radius: f32) -> GameResult<Self> {
+    pub fn load(ctx: &mut Context, mesh_raster_scale: f32, scenery: &Scenery) -> GameResult<Self> {
         let arena = Mesh::new_circle(
             ctx,
             DrawMode::stroke(1. * mesh_raster_scale),
             [0., 0.],
-            arena_radius * mesh_raster_scale,
+            scenery.arena_radius * mesh_raster_scale,
             2.,
             Color::BLACK,
         )?;
@@ -35,8 +73,63 @@ impl Assets {
             Color::new(0., 0., 0., 0.2), // Grey
         )?;
 
-        Ok(Assets { arena, boi, vision })
+        let obstacles = shapes_mesh(
+            ctx,
+            mesh_raster_scale,
+            scenery
+                .obstacles
+                .iter()
+                .map(|shape| (shape, Color::from_rgb(90, 80, 70))),
+        )?;
+        let biomes = shapes_mesh(
+            ctx,
+            mesh_raster_scale,
+            scenery
+                .biomes
+                .iter()
+                .map(|(shape, [r, g, b])| (shape, Color::from_rgb(*r, *g, *b))),
+        )?;
+
+        Ok(Assets {
+            arena,
+            boi,
+            vision,
+            obstacles,
+            biomes,
+        })
+    }
+}
+
+/// Filled in shapes, all in one mesh. None if there aren't any shapes.
+fn shapes_mesh<'a>(
+    ctx: &mut Context,
+    mesh_raster_scale: f32,
+    shapes: impl Iterator<Item = (&'a Shape, Color)>,
+) -> GameResult<Option<Mesh>> {
+    let mut builder = MeshBuilder::new();
+    let mut empty = true;
+    for (shape, colour) in shapes {
+        match shape {
+            Shape::Circle { centre, radius } => builder.circle(
+                DrawMode::fill(),
+                [centre.x * mesh_raster_scale, centre.y * mesh_raster_scale],
+                radius * mesh_raster_scale,
+                2.,
+                colour,
+            )?,
+            Shape::Polygon { points } => builder.polygon(
+                DrawMode::fill(),
+                &points
+                    .iter()
+                    .map(|p| [p.x * mesh_raster_scale, p.y * mesh_raster_scale])
+                    .collect::<Vec<_>>(),
+                colour,
+            )?,
+        };
+        empty = false;
     }
+
+    Ok((!empty).then(|| Mesh::from_data(ctx, builder.build())))
 }
 
 /// A single structure to hold all the info about rendering
@@ -47,13 +140,270 @@ pub struct RenderState {
     pub mesh_raster_scale: f32,
     pub screen_scale: f32, // difference between world scale and draw scale
     pub padding: f32,      // padding around edge of world in pixels
-    pub fps: u32,
-    pub needs_render: bool,
+    pub arena_centre: Vec2,
+    pub arena_radius: f32,
+}
+
+/// Everything needed to draw a single boi
+pub struct BoiSprite {
+    pub position: Vec2,
+    pub direction: f32,
+    pub colour: Color,
+    pub vision: f32,
+    pub fov: f32,
 }
 
 impl RenderState {
+    pub fn new(ctx: &mut Context, config: &RenderConfig, scenery: &Scenery) -> GameResult<Self> {
+        // Load all the assets once at the start
+        let mesh_raster_scale = 100.;
+        let assets = Assets::load(ctx, mesh_raster_scale, scenery)?;
+
+        Ok(Self {
+            assets,
+            screen_scale: config.screen_scale,
+            padding: config.padding,
+            mesh_raster_scale,
+            arena_centre: scenery.arena_centre,
+            arena_radius: scenery.arena_radius,
+        })
+    }
+
+    /// Converts a position in world space to canvas space
+    pub fn world_to_canvas(&self, vec: &Vec2) -> Vec2 {
+        vec.add_scalar(self.arena_radius)
+            .mul(self.screen_scale)
+            .add_scalar(self.padding)
+    }
+
+    /// Converts a position in canvas space to world space
+    pub fn canvas_to_world(&self, vec: &Vec2) -> Vec2 {
+        vec.add_scalar(-self.padding)
+            .mul(1. / self.screen_scale)
+            .add_scalar(-self.arena_radius)
+    }
+
+    /// Draw the arena & everyone in it, along with the food if there is any
+    pub fn draw_world(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        food: Option<&Food>,
+        bois: impl Iterator<Item = BoiSprite>,
+    ) -> GameResult {
+        // Biomes go underneath everything else
+        let origin = self.world_to_canvas(&Vec2::new(0., 0.));
+        if let Some(biomes) = &self.assets.biomes {
+            canvas.draw(
+                biomes,
+                DrawParam::default()
+                    .dest([origin.x, origin.y])
+                    .scale([self.base_scale(), self.base_scale()]),
+            );
+        }
+
+        // Then the food, greener the more there is
+        if let Some(food) = food {
+            let mut builder = MeshBuilder::new();
+            let mut empty = true;
+            let size = food.config.cell_size * self.screen_scale;
+            for (centre, fullness) in food.iter() {
+                if centre.distance(&self.arena_centre) > self.arena_radius || fullness <= 0. {
+                    continue;
+                }
+                let corner = self.world_to_canvas(&centre).add_scalar(-size / 2.);
+                builder.rectangle(
+                    DrawMode::fill(),
+                    Rect::new(corner.x, corner.y, size, size),
+                    Color::new(0.1, 0.5, 0.1, 0.4 * fullness),
+                )?;
+                empty = false;
+            }
+            if !empty {
+                canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+            }
+        }
+
+        // Debug - Arena boundaries
+        let arena_pos = self.world_to_canvas(&self.arena_centre);
+        canvas.draw(
+            &self.assets.arena,
+            DrawParam::default()
+                .dest([arena_pos.x, arena_pos.y])
+                .scale([self.base_scale(), self.base_scale()]),
+        );
+
+        if let Some(obstacles) = &self.assets.obstacles {
+            canvas.draw(
+                obstacles,
+                DrawParam::default()
+                    .dest([origin.x, origin.y])
+                    .scale([self.base_scale(), self.base_scale()]),
+            );
+        }
+
+        let bbox = self.assets.boi.dimensions(ctx).unwrap().size();
+        let mut cones = MeshBuilder::new();
+        let mut any_cones = false;
+        for boi in bois {
+            // Draw boi
+            let position = self.world_to_canvas(&boi.position);
+            canvas.draw(
+                &self.assets.boi,
+                DrawParam::default()
+                    .dest([position.x, position.y])
+                    // +PI/2 since our image is 90 degrees rotated left
+                    .rotation(boi.direction + PI / 2.)
+                    // Align image centre with Boi centre
+                    .offset([0.5, 0.5])
+                    // Handle scaling specifically for this image (see asset loading section)
+                    .scale([
+                        10. * self.screen_scale / bbox.x,
+                        10. * self.screen_scale / bbox.y,
+                    ])
+                    // Change the colour depending on the species
+                    .color(boi.colour),
+            );
+
+            // Debug - boi vision. A circle if it can see all round, otherwise a cone.
+            if boi.fov >= TAU {
+                canvas.draw(
+                    &self.assets.vision,
+                    DrawParam::default().dest([position.x, position.y]).scale([
+                        self.base_scale() * boi.vision,
+                        self.base_scale() * boi.vision,
+                    ]),
+                );
+            } else {
+                let radius = boi.vision * self.screen_scale;
+                let steps = (boi.fov / TAU * 32.).ceil().max(1.) as usize;
+                let arc = (0..=steps).map(|i| {
+                    let angle = boi.direction - boi.fov / 2. + boi.fov * i as f32 / steps as f32;
+                    [
+                        position.x + angle.cos() * radius,
+                        position.y + angle.sin() * radius,
+                    ]
+                });
+                let outline = [[position.x, position.y]]
+                    .into_iter()
+                    .chain(arc)
+                    .chain([[position.x, position.y]])
+                    .collect::<Vec<_>>();
+                cones.line(
+                    &outline,
+                    0.1 * self.screen_scale,
+                    Color::new(0., 0., 0., 0.2),
+                )?;
+                any_cones = true;
+            }
+        }
+        if any_cones {
+            canvas.draw(&Mesh::from_data(ctx, cones.build()), DrawParam::default());
+        }
+
+        Ok(())
+    }
+
+    /// Debug - Lines from a boi to everyone in its field of view, green if it can see them & red if
+    /// they're hidden
+    pub fn draw_sight_lines(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        from: &Vec2,
+        lines: &[(Vec2, bool)],
+    ) -> GameResult {
+        let from = self.world_to_canvas(from);
+        let lines = lines
+            .iter()
+            .map(|(to, visible)| (self.world_to_canvas(to), *visible))
+            // Can't draw a line with no length
+            .filter(|(to, _)| to.distance(&from) > 0.1)
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let mut builder = MeshBuilder::new();
+        for (to, visible) in lines {
+            let colour = if visible {
+                Color::new(0., 0.6, 0., 0.8)
+            } else {
+                Color::new(0.8, 0., 0., 0.8)
+            };
+            builder.line(&[[from.x, from.y], [to.x, to.y]], 1.5, colour)?;
+        }
+        canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+
+        Ok(())
+    }
+
+    /// Debug - Arrows showing which way the flow is going across the arena. Each arrow is as long
+    /// as the flow would carry a boi in a second.
+    pub fn draw_flow(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        flow: impl Fn(&Vec2) -> Vec2,
+    ) -> GameResult {
+        let mut builder = MeshBuilder::new();
+        let mut empty = true;
+        let steps = (self.arena_radius / FLOW_ARROW_SPACING) as i32;
+        for i in -steps..=steps {
+            for j in -steps..=steps {
+                let tail = self.arena_centre.add(&Vec2::new(
+                    i as f32 * FLOW_ARROW_SPACING,
+                    j as f32 * FLOW_ARROW_SPACING,
+                ));
+                let velocity = flow(&tail);
+                if tail.distance(&self.arena_centre) > self.arena_radius
+                    || velocity.distance(&Vec2::new(0., 0.)) < 1e-3
+                {
+                    continue;
+                }
+
+                let head = tail.add(&velocity);
+                let back = velocity.normalise().mul(-1.);
+                let barb = |angle: f32| {
+                    let (sin, cos) = angle.sin_cos();
+                    head.add(&Vec2::new(
+                        back.x * cos - back.y * sin,
+                        back.x * sin + back.y * cos,
+                    ))
+                };
+                let [tail, head, left, right] = [tail, head, barb(0.5), barb(-0.5)].map(|p| {
+                    let p = self.world_to_canvas(&p);
+                    [p.x, p.y]
+                });
+                let colour = Color::new(0., 0., 0.5, 0.5);
+                builder.line(&[tail, head], 1., colour)?;
+                builder.line(&[left, head, right], 1., colour)?;
+                empty = false;
+            }
+        }
+
+        if !empty {
+            canvas.draw(&Mesh::from_data(ctx, builder.build()), DrawParam::default());
+        }
+        Ok(())
+    }
+
     /// Base rendering scale used for Meshes
     pub fn base_scale(&self) -> f32 {
         self.screen_scale / self.mesh_raster_scale
     }
 }
+
+/// Colour each species is drawn in
+pub fn species_colour(species: &Species) -> Color {
+    let [r, g, b] = species.colour;
+    Color::from_rgb(r, g, b)
+}
+
+/// Position & direction somewhere between two ticks. `alpha` is how far from the previous to the
+/// next we are.
+pub fn interpolate(previous: (Vec2, f32), next: (Vec2, f32), alpha: f32) -> (Vec2, f32) {
+    // Go the short way round
+    let delta = (next.1 - previous.1 + PI).rem_euclid(2. * PI) - PI;
+    (previous.0.lerp(&next.0, alpha), previous.1 + delta * alpha)
+}
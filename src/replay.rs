@@ -0,0 +1,264 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::EntityId,
+    render::Scenery,
+    species::{Species, SpeciesId},
+    vec::Vec2,
+    world::World,
+};
+
+/// Bumped whenever the replay layout changes, so old files get rejected instead of misread
+const REPLAY_VERSION: u32 = 4;
+
+/// A recording of a whole run, which can be played back without re-running the simulation.
+///
+/// Only what's needed to draw each tick gets stored. Anything that never changes over a boi's life
+/// (species, vision, field of view) is stored once when it spawns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    version: u32,
+    pub scenery: Scenery,
+    pub tick_rate: u32,
+    pub species: Vec<Species>,
+    pub frames: Vec<Frame>, // The first frame is the world as it was when recording started
+}
+
+/// Everything that happened on a single tick
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub tick: u64,
+    pub bois: Vec<BoiFrame>,
+    pub spawns: Vec<Spawn>,
+    pub deaths: Vec<Death>,
+}
+
+/// Where a boi was at the end of a tick
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoiFrame {
+    pub id: EntityId,
+    pub position: Vec2,
+    pub direction: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Spawn {
+    pub id: EntityId,
+    pub species: SpeciesId,
+    pub vision: f32,
+    pub fov: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Death {
+    pub id: EntityId,
+    pub killer: Option<EntityId>, // None if it died of something other than being eaten
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Encode(bincode::error::EncodeError),
+    Decode(bincode::error::DecodeError),
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "Couldn't access replay file: {}", e),
+            ReplayError::Encode(e) => write!(f, "Couldn't write replay: {}", e),
+            ReplayError::Decode(e) => write!(f, "Couldn't read replay: {}", e),
+            ReplayError::Version(v) => write!(
+                f,
+                "Replay is version {}, but only version {} is supported",
+                v, REPLAY_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    /// Save the replay to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        let file = fs::File::create(path).map_err(ReplayError::Io)?;
+        bincode::serde::encode_into_std_write(
+            self,
+            &mut BufWriter::new(file),
+            bincode::config::standard(),
+        )
+        .map_err(ReplayError::Encode)?;
+
+        Ok(())
+    }
+
+    /// Load a replay saved with [Replay::save]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let file = fs::File::open(path).map_err(ReplayError::Io)?;
+        let replay: Self = bincode::serde::decode_from_std_read(
+            &mut BufReader::new(file),
+            bincode::config::standard(),
+        )
+        .map_err(ReplayError::Decode)?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+        Ok(replay)
+    }
+
+    /// Every boi that ever spawned in the replay. IDs are never handed out twice in a world, and
+    /// a recording can't switch worlds part way through, so each one only spawns once.
+    pub fn spawns(&self) -> HashMap<EntityId, Spawn> {
+        let mut spawns = HashMap::new();
+        for spawn in self.frames.iter().flat_map(|frame| &frame.spawns) {
+            let earlier = spawns.insert(spawn.id, *spawn);
+            assert!(
+                earlier.is_none(),
+                "Boi {} spawned twice in the replay",
+                spawn.id
+            );
+        }
+        spawns
+    }
+}
+
+/// Builds up a [Replay] one tick at a time
+pub struct Recorder {
+    replay: Replay,
+    alive: HashSet<EntityId>, // Everyone alive as of the last recorded frame
+}
+
+impl Recorder {
+    /// Start recording from the world's current state
+    pub fn new(world: &World) -> Self {
+        let mut recorder = Self {
+            replay: Replay {
+                version: REPLAY_VERSION,
+                scenery: Scenery::of(world),
+                tick_rate: world.config.world.tick_rate,
+                species: world.species.iter().map(|(_, s)| s.clone()).collect(),
+                frames: vec![],
+            },
+            alive: HashSet::new(),
+        };
+        recorder.record(world);
+        recorder
+    }
+
+    /// Record the world as it is after a step
+    pub fn record(&mut self, world: &World) {
+        let spawns = world
+            .bois
+            .iter()
+            .filter(|boi| !self.alive.contains(&boi.id))
+            .map(|boi| Spawn {
+                id: boi.id,
+                species: boi.species,
+                vision: boi.vision,
+                fov: boi.fov,
+            })
+            .collect::<Vec<_>>();
+
+        // Work out who's gone by comparing against the last frame rather than trusting the kill
+        // list, so bois that starve get recorded too
+        let now_alive = world.bois.iter().map(|boi| boi.id).collect::<HashSet<_>>();
+        let mut deaths = self
+            .alive
+            .difference(&now_alive)
+            .map(|&id| Death {
+                id,
+                killer: world
+                    .kills
+                    .iter()
+                    .find(|kill| kill.prey == id)
+                    .map(|kill| kill.predator),
+            })
+            .collect::<Vec<_>>();
+        deaths.sort_by_key(|death| death.id);
+
+        self.replay.frames.push(Frame {
+            tick: world.tick,
+            bois: world
+                .bois
+                .iter()
+                .map(|boi| BoiFrame {
+                    id: boi.id,
+                    position: boi.position,
+                    direction: boi.direction,
+                })
+                .collect(),
+            spawns,
+            deaths,
+        });
+        self.alive = now_alive;
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        self.replay.save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn recording_round_trips() {
+        let mut config = Config::default();
+        config.world.num_bois = 300;
+        config.world.arena_radius = 30.;
+
+        let mut world = World::new(&config, 5);
+        let mut recorder = Recorder::new(&world);
+        let mut kills = vec![];
+        for _ in 0..60 {
+            world.step(1. / 30.);
+            recorder.record(&world);
+            kills.extend(world.kills.iter().map(|kill| (kill.prey, kill.predator)));
+        }
+
+        let path = std::env::temp_dir().join("bois_replay_test.replay");
+        recorder.save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(replay.frames.len(), 61);
+        assert_eq!(replay.frames[0].spawns.len(), 300);
+        assert_eq!(replay.spawns().len(), 300);
+
+        let last = replay.frames.last().unwrap();
+        assert_eq!(last.tick, world.tick);
+        assert_eq!(last.bois.len(), world.bois.len());
+        assert!(last
+            .bois
+            .iter()
+            .zip(&world.bois)
+            .all(|(frame, boi)| frame.id == boi.id && frame.position == boi.position));
+
+        // Every kill should show up as a death, blamed on the right predator
+        let mut deaths = replay
+            .frames
+            .iter()
+            .flat_map(|frame| &frame.deaths)
+            .map(|death| (death.id, death.killer.unwrap()))
+            .collect::<Vec<_>>();
+        deaths.sort();
+        kills.sort();
+        assert_eq!(deaths, kills);
+    }
+}
@@ -0,0 +1,297 @@
+use geo_index::rtree::{sort::HilbertSort, RTree, RTreeBuilder, RTreeIndex};
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec2;
+
+/// A shape as it's written in the scenario file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeConfig {
+    Circle {
+        centre: [f32; 2],
+        radius: f32,
+    },
+    /// Any simple polygon, convex or not. Points can go either way round.
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Circle { centre: Vec2, radius: f32 },
+    Polygon { points: Vec<Vec2> },
+}
+
+impl From<&ShapeConfig> for Shape {
+    fn from(config: &ShapeConfig) -> Self {
+        match config {
+            ShapeConfig::Circle { centre, radius } => Shape::Circle {
+                centre: Vec2::new(centre[0], centre[1]),
+                radius: *radius,
+            },
+            ShapeConfig::Polygon { points } => Shape::Polygon {
+                points: points.iter().map(|[x, y]| Vec2::new(*x, *y)).collect(),
+            },
+        }
+    }
+}
+
+/// Where a ray first hits a shape
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub distance: f32,
+    pub normal: Vec2, // Pointing out of the shape
+}
+
+impl Shape {
+    /// Bottom left & top right corners of the bounding box
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match self {
+            Shape::Circle { centre, radius } => {
+                (centre.add_scalar(-radius), centre.add_scalar(*radius))
+            }
+            Shape::Polygon { points } => points.iter().fold(
+                (
+                    Vec2::new(f32::INFINITY, f32::INFINITY),
+                    Vec2::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+                ),
+                |(min, max), p| {
+                    (
+                        Vec2::new(min.x.min(p.x), min.y.min(p.y)),
+                        Vec2::new(max.x.max(p.x), max.y.max(p.y)),
+                    )
+                },
+            ),
+        }
+    }
+
+    pub fn contains(&self, point: &Vec2) -> bool {
+        match self {
+            Shape::Circle { centre, radius } => centre.distance(point) < *radius,
+            Shape::Polygon { points } => {
+                // Count how many edges a ray going right from the point crosses
+                edges(points)
+                    .filter(|(a, b)| {
+                        (a.y > point.y) != (b.y > point.y)
+                            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    })
+                    .count()
+                    % 2
+                    == 1
+            }
+        }
+    }
+
+    /// Closest point on the edge of the shape to a point inside it, and the direction out
+    pub fn way_out(&self, point: &Vec2) -> (Vec2, Vec2) {
+        match self {
+            Shape::Circle { centre, radius } => {
+                let mut out = point.sub(centre).normalise();
+                if out == Vec2::new(0., 0.) {
+                    // Dead centre, so any way out will do
+                    out = Vec2::new(1., 0.);
+                }
+                (centre.add(&out.mul(*radius)), out)
+            }
+            Shape::Polygon { points } => {
+                let winding = winding(points);
+                let (closest, normal) = edges(points)
+                    .map(|(a, b)| (closest_on_segment(point, a, b), edge_normal(a, b, winding)))
+                    .min_by(|(p1, _), (p2, _)| p1.distance(point).total_cmp(&p2.distance(point)))
+                    .expect("Polygons have at least 3 points");
+
+                // Straight towards the closest point is the way out, unless we're right on it
+                let out = closest.sub(point).normalise();
+                if out == Vec2::new(0., 0.) {
+                    (closest, normal)
+                } else {
+                    (closest, out)
+                }
+            }
+        }
+    }
+
+    /// First place a ray from `origin` in `direction` (a unit vector) hits the shape, if it's
+    /// within `length`
+    pub fn raycast(&self, origin: &Vec2, direction: &Vec2, length: f32) -> Option<Hit> {
+        match self {
+            Shape::Circle { centre, radius } => {
+                // Solve |origin + t * direction - centre| = radius for t
+                let offset = origin.sub(centre);
+                let b = offset.x * direction.x + offset.y * direction.y;
+                let c = offset.x.powi(2) + offset.y.powi(2) - radius.powi(2);
+                let discriminant = b * b - c;
+                if discriminant < 0. {
+                    return None;
+                }
+                let distance = -b - discriminant.sqrt();
+                (0. ..=length).contains(&distance).then(|| {
+                    let hit = origin.add(&direction.mul(distance));
+                    Hit {
+                        distance,
+                        normal: hit.sub(centre).normalise(),
+                    }
+                })
+            }
+            Shape::Polygon { points } => {
+                let winding = winding(points);
+                edges(points)
+                    .filter_map(|(a, b)| {
+                        let distance = ray_segment(origin, direction, a, b)?;
+                        (distance <= length).then(|| Hit {
+                            distance,
+                            normal: edge_normal(a, b, winding),
+                        })
+                    })
+                    .min_by(|h1, h2| h1.distance.total_cmp(&h2.distance))
+            }
+        }
+    }
+}
+
+/// Each edge of a polygon, including the one joining the last point back to the first
+fn edges(points: &[Vec2]) -> impl Iterator<Item = (&Vec2, &Vec2)> {
+    points.iter().zip(points.iter().cycle().skip(1))
+}
+
+/// Twice the signed area of a polygon. Positive if the points go anticlockwise.
+pub fn signed_area(points: &[Vec2]) -> f32 {
+    edges(points).map(|(a, b)| a.x * b.y - b.x * a.y).sum()
+}
+
+fn winding(points: &[Vec2]) -> f32 {
+    signed_area(points).signum()
+}
+
+/// Unit normal of an edge, pointing out of the polygon
+fn edge_normal(a: &Vec2, b: &Vec2, winding: f32) -> Vec2 {
+    Vec2::new(b.y - a.y, a.x - b.x).mul(winding).normalise()
+}
+
+pub fn closest_on_segment(point: &Vec2, a: &Vec2, b: &Vec2) -> Vec2 {
+    let ab = b.sub(a);
+    let length_squared = ab.x.powi(2) + ab.y.powi(2);
+    if length_squared == 0. {
+        return *a;
+    }
+    let ap = point.sub(a);
+    let t = ((ap.x * ab.x + ap.y * ab.y) / length_squared).clamp(0., 1.);
+    a.add(&ab.mul(t))
+}
+
+/// How far along the ray it crosses the segment, if it does
+fn ray_segment(origin: &Vec2, direction: &Vec2, a: &Vec2, b: &Vec2) -> Option<f32> {
+    let ab = b.sub(a);
+    let cross = direction.x * ab.y - direction.y * ab.x;
+    if cross == 0. {
+        // Parallel
+        return None;
+    }
+    let ao = a.sub(origin);
+    let t = (ao.x * ab.y - ao.y * ab.x) / cross; // Along the ray
+    let u = (ao.x * direction.y - ao.y * direction.x) / cross; // Along the segment
+    (t >= 0. && (0. ..=1.).contains(&u)).then_some(t)
+}
+
+/// A bunch of shapes, with an R-tree over their bounding boxes so lookups only have to check the
+/// ones nearby
+pub struct ShapeIndex {
+    shapes: Vec<Shape>,
+    tree: Option<RTree<f32>>, // None if there aren't any shapes
+}
+
+impl ShapeIndex {
+    pub fn new(shapes: Vec<Shape>) -> Self {
+        // The tree can't be built empty
+        let tree = (!shapes.is_empty()).then(|| {
+            let mut builder = RTreeBuilder::new(shapes.len() as u32);
+            shapes.iter().for_each(|shape| {
+                let (min, max) = shape.bounds();
+                builder.add(min.x, min.y, max.x, max.y);
+            });
+            builder.finish::<HilbertSort>()
+        });
+
+        Self { shapes, tree }
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    /// Shapes whose bounding box overlaps the given box, along with their index, in order
+    pub fn near(&self, min: &Vec2, max: &Vec2) -> impl Iterator<Item = (usize, &Shape)> {
+        let mut found = match &self.tree {
+            Some(tree) => tree.search(min.x, min.y, max.x, max.y),
+            None => vec![],
+        };
+        found.sort();
+        found
+            .into_iter()
+            .map(|i| (i as usize, &self.shapes[i as usize]))
+    }
+
+    /// Shapes the point is inside, along with their index, in order
+    pub fn containing<'a>(&'a self, point: &'a Vec2) -> impl Iterator<Item = (usize, &'a Shape)> {
+        self.near(point, point)
+            .filter(move |(_, shape)| shape.contains(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concave_polygon() {
+        // A U shape, open at the top
+        let cup = ShapeConfig::Polygon {
+            points: vec![
+                [0., 0.],
+                [6., 0.],
+                [6., 6.],
+                [4., 6.],
+                [4., 2.],
+                [2., 2.],
+                [2., 6.],
+                [0., 6.],
+            ],
+        };
+        let cup = Shape::from(&cup);
+
+        assert!(cup.contains(&Vec2::new(1., 1.)));
+        assert!(cup.contains(&Vec2::new(5., 5.)));
+        // In the hollow
+        assert!(!cup.contains(&Vec2::new(3., 4.)));
+        assert!(!cup.contains(&Vec2::new(-1., 1.)));
+    }
+
+    #[test]
+    fn index_finds_containing_shapes_in_order() {
+        let index = ShapeIndex::new(vec![
+            Shape::Circle {
+                centre: Vec2::new(0., 0.),
+                radius: 10.,
+            },
+            Shape::Circle {
+                centre: Vec2::new(50., 0.),
+                radius: 10.,
+            },
+            Shape::Circle {
+                centre: Vec2::new(5., 0.),
+                radius: 10.,
+            },
+        ]);
+
+        let found = |x, y| {
+            index
+                .containing(&Vec2::new(x, y))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found(3., 0.), vec![0, 2]);
+        assert_eq!(found(50., 0.), vec![1]);
+        assert_eq!(found(30., 0.), Vec::<usize>::new());
+    }
+}
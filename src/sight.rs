@@ -0,0 +1,118 @@
+//! Line of sight. Optionally, bois can't see through obstacles or each other.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{boi::Boi, shape::closest_on_segment, world::World};
+
+/// What blocks a boi's view. Nothing does by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OcclusionConfig {
+    pub obstacles: bool,  // Can't see through obstacles
+    pub bois: bool,       // Can't see through other bois
+    pub body_radius: f32, // How big a boi is for blocking the view
+}
+
+impl Default for OcclusionConfig {
+    fn default() -> Self {
+        Self {
+            obstacles: false,
+            bois: false,
+            body_radius: 0.5,
+        }
+    }
+}
+
+impl OcclusionConfig {
+    pub fn enabled(&self) -> bool {
+        self.obstacles || self.bois
+    }
+}
+
+/// Whether `viewer`'s view of `target` is blocked. `bodies` is everyone who might be in the way.
+pub fn hidden(world: &World, viewer: &Boi, target: &Boi, bodies: &[&Boi]) -> bool {
+    let occlusion = &world.config.occlusion;
+    let offset = target.position.sub(&viewer.position);
+    let distance = viewer.position.distance(&target.position);
+
+    if occlusion.obstacles
+        && world
+            .obstacles
+            .raycast(&viewer.position, &offset.normalise(), distance)
+            .is_some()
+    {
+        return true;
+    }
+
+    occlusion.bois
+        && bodies.iter().any(|body| {
+            body.id != viewer.id
+                && body.id != target.id
+                && body.position.distance(&closest_on_segment(
+                    &body.position,
+                    &viewer.position,
+                    &target.position,
+                )) < occlusion.body_radius
+                // Only if it's actually in between, not just overlapping either end
+                && body.position.distance(&viewer.position) < distance
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, shape::ShapeConfig, vec::Vec2};
+
+    /// Who the first boi can see, out of everyone else
+    fn seen(config: &Config, positions: &[(f32, f32)]) -> Vec<u64> {
+        let mut world = World::new(config, 1);
+        world.bois = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y))| Boi::test_at(i as u64, 1, Vec2::new(x, y), 0.))
+            .collect();
+        world.boi_index = config
+            .world
+            .spatial_index
+            .build(&world.bois.iter().map(|b| b.position).collect::<Vec<_>>());
+
+        let mut seen = world.bois[0]
+            .look(&world, 20.)
+            .into_iter()
+            .filter(|(_, _, hidden)| !hidden)
+            .map(|(boi, _, _)| boi.id)
+            .collect::<Vec<_>>();
+        seen.sort();
+        seen
+    }
+
+    #[test]
+    fn hiding_behind_a_rock() {
+        let mut config = Config::default();
+        config.world.num_bois = 0;
+        config.obstacles = vec![ShapeConfig::Circle {
+            centre: [5., 0.],
+            radius: 2.,
+        }];
+        let bois = [(0., 0.), (10., 0.), (10., 5.), (2.5, 0.)];
+
+        // Everyone's visible until occlusion is turned on
+        assert_eq!(seen(&config, &bois), vec![1, 2, 3]);
+        config.occlusion.obstacles = true;
+        assert_eq!(seen(&config, &bois), vec![2, 3]);
+    }
+
+    #[test]
+    fn hiding_in_the_crowd() {
+        let mut config = Config::default();
+        config.world.num_bois = 0;
+        // Someone right behind a neighbour, & someone off to the side
+        let bois = [(0., 0.), (2., 0.), (4., 0.1), (4., 3.)];
+
+        assert_eq!(seen(&config, &bois), vec![1, 2, 3]);
+        config.occlusion.bois = true;
+        assert_eq!(seen(&config, &bois), vec![1, 3]);
+        config.occlusion.body_radius = 0.;
+        assert_eq!(seen(&config, &bois), vec![1, 2, 3]);
+    }
+}
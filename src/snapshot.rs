@@ -0,0 +1,155 @@
+use std::{
+    fmt, fs,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    boi::Boi,
+    config::{Config, ConfigError},
+    entity::EntityId,
+    world::{build_nest, SimRng, World},
+};
+
+/// Bumped whenever the snapshot layout changes, so old files get rejected instead of misread
+const SNAPSHOT_VERSION: u32 = 6;
+
+/// Everything needed to carry on a simulation exactly where it left off
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    // Stored as TOML rather than directly since the config uses serde features that a binary
+    // format can't handle
+    scenario: String,
+    tick: u64,
+    bois: Vec<Boi>,
+    rng: SimRng,
+    next_id: EntityId,
+    food: Option<Vec<f32>>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Encode(bincode::error::EncodeError),
+    Decode(bincode::error::DecodeError),
+    Scenario(String),
+    Config(ConfigError),
+    Version(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "Couldn't access snapshot file: {}", e),
+            SnapshotError::Encode(e) => write!(f, "Couldn't write snapshot: {}", e),
+            SnapshotError::Decode(e) => write!(f, "Couldn't read snapshot: {}", e),
+            SnapshotError::Scenario(e) => write!(f, "Couldn't store the scenario: {}", e),
+            SnapshotError::Config(e) => write!(f, "Snapshot has a bad scenario: {}", e),
+            SnapshotError::Version(v) => write!(
+                f,
+                "Snapshot is version {}, but only version {} is supported",
+                v, SNAPSHOT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl World {
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError> {
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            scenario: toml::to_string(&self.config)
+                .map_err(|e| SnapshotError::Scenario(e.to_string()))?,
+            tick: self.tick,
+            bois: self.bois.clone(),
+            rng: self.nest.rng.clone(),
+            next_id: self.nest.next_id,
+            food: self.food.as_ref().map(|food| food.cells().to_vec()),
+        })
+    }
+
+    pub fn from_snapshot(snapshot: Snapshot) -> Result<Self, SnapshotError> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(snapshot.version));
+        }
+
+        let config = Config::from_toml(&snapshot.scenario).map_err(SnapshotError::Config)?;
+        let nest = build_nest(&config, snapshot.rng, snapshot.next_id);
+
+        let mut world = Self::from_parts(&config, snapshot.bois, nest, snapshot.tick);
+        if let (Some(food), Some(cells)) = (&mut world.food, snapshot.food) {
+            food.restore(cells);
+        }
+
+        Ok(world)
+    }
+
+    /// Save the whole world to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let file = fs::File::create(path).map_err(SnapshotError::Io)?;
+        bincode::serde::encode_into_std_write(
+            self.snapshot()?,
+            &mut BufWriter::new(file),
+            bincode::config::standard(),
+        )
+        .map_err(SnapshotError::Encode)?;
+
+        Ok(())
+    }
+
+    /// Load a world saved with [World::save]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let file = fs::File::open(path).map_err(SnapshotError::Io)?;
+        let snapshot = bincode::serde::decode_from_std_read(
+            &mut BufReader::new(file),
+            bincode::config::standard(),
+        )
+        .map_err(SnapshotError::Decode)?;
+
+        Self::from_snapshot(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_world_carries_on_identically() {
+        let mut config = Config::default();
+        config.world.num_bois = 300;
+        config.world.arena_radius = 50.;
+
+        let mut original = World::new(&config, 3);
+        for _ in 0..30 {
+            original.step(1. / 30.);
+        }
+
+        let path = std::env::temp_dir().join("bois_snapshot_test.bois");
+        original.save(&path).unwrap();
+        let mut loaded = World::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.tick, original.tick);
+        for _ in 0..30 {
+            original.step(1. / 30.);
+            loaded.step(1. / 30.);
+
+            let state = |world: &World| {
+                world
+                    .bois
+                    .iter()
+                    .map(|boi| (boi.id, boi.position, boi.direction))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(state(&original), state(&loaded));
+        }
+        assert_eq!(original.nest.next_id, loaded.nest.next_id);
+        assert_eq!(original.nest.rng, loaded.nest.rng);
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+
+use geo_index::kdtree::{KDTree, KDTreeBuilder, KDTreeIndex};
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec2;
+
+/// A point found by a spatial query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbour {
+    pub index: usize, // Index into the positions the index was last updated with
+    pub distance: f32,
+}
+
+/// Something that can quickly find points near other points.
+///
+/// All backends return the same neighbours in the same order for the same positions, so swapping
+/// backend doesn't change the outcome of a simulation.
+pub trait SpatialIndex: Send + Sync {
+    /// Bring the index up to date with the current positions
+    fn update(&mut self, positions: &[Vec2]);
+
+    /// Everything within `radius` of `centre`, sorted by index
+    fn within(&self, centre: &Vec2, radius: f32) -> Vec<Neighbour>;
+
+    /// The `k` closest points to `centre`, sorted by distance (then by index)
+    fn nearest(&self, centre: &Vec2, k: usize) -> Vec<Neighbour>;
+}
+
+/// Which spatial index backend to use
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SpatialIndexConfig {
+    /// K-D tree that gets rebuilt from scratch on every update
+    #[default]
+    KdTree,
+    /// Uniform grid that only moves the points which changed cell
+    Grid { cell_size: f32 },
+}
+
+impl SpatialIndexConfig {
+    pub fn build(&self, positions: &[Vec2]) -> Box<dyn SpatialIndex> {
+        let mut index: Box<dyn SpatialIndex> = match self {
+            Self::KdTree => Box::new(KdTreeIndex::default()),
+            Self::Grid { cell_size } => Box::new(GridIndex::new(*cell_size)),
+        };
+        index.update(positions);
+        index
+    }
+}
+
+/// Sort neighbours by distance, breaking ties on index so the order is always the same
+fn sort_by_distance(neighbours: &mut [Neighbour]) {
+    neighbours.sort_by(|a, b| {
+        a.distance
+            .total_cmp(&b.distance)
+            .then(a.index.cmp(&b.index))
+    });
+}
+
+/// Static K-D tree, rebuilt every time the positions change
+#[derive(Default)]
+pub struct KdTreeIndex {
+    tree: Option<KDTree<f32>>,
+    positions: Vec<Vec2>,
+}
+
+impl SpatialIndex for KdTreeIndex {
+    fn update(&mut self, positions: &[Vec2]) {
+        self.positions = positions.to_vec();
+
+        // The tree can't be built empty
+        self.tree = if positions.is_empty() {
+            None
+        } else {
+            let mut builder = KDTreeBuilder::new(positions.len() as u32);
+            positions.iter().for_each(|pos| {
+                builder.add(pos.x, pos.y);
+            });
+            Some(builder.finish())
+        };
+    }
+
+    fn within(&self, centre: &Vec2, radius: f32) -> Vec<Neighbour> {
+        let Some(tree) = &self.tree else {
+            return vec![];
+        };
+
+        let mut found = tree
+            .within(centre.x, centre.y, radius)
+            .into_iter()
+            .map(|i| Neighbour {
+                index: i as usize,
+                distance: centre.distance(&self.positions[i as usize]),
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_key(|n| n.index);
+
+        found
+    }
+
+    fn nearest(&self, centre: &Vec2, k: usize) -> Vec<Neighbour> {
+        if k == 0 || self.positions.is_empty() {
+            return vec![];
+        }
+
+        // The tree only supports radius queries, so keep widening the search until we've got
+        // enough points
+        let mut radius = 1.;
+        loop {
+            let mut found = self.within(centre, radius);
+            if found.len() >= k || found.len() == self.positions.len() {
+                sort_by_distance(&mut found);
+                found.truncate(k);
+                return found;
+            }
+            radius *= 2.;
+        }
+    }
+}
+
+type Cell = (i32, i32);
+
+/// Uniform grid / spatial hash. Points are bucketed into square cells, and only points that move
+/// between cells need touching on an update.
+pub struct GridIndex {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<usize>>,
+    positions: Vec<Vec2>,
+    point_cells: Vec<Cell>, // Which cell each point is currently in
+}
+
+impl GridIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            positions: vec![],
+            point_cells: vec![],
+        }
+    }
+
+    fn cell(&self, pos: &Vec2) -> Cell {
+        (
+            (pos.x / self.cell_size).floor() as i32,
+            (pos.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Throw everything away and start from scratch
+    fn rebuild(&mut self, positions: &[Vec2]) {
+        self.cells.clear();
+        self.point_cells = positions.iter().map(|pos| self.cell(pos)).collect();
+        self.point_cells
+            .iter()
+            .enumerate()
+            .for_each(|(i, cell)| self.cells.entry(*cell).or_default().push(i));
+    }
+
+    /// Points in the cells between the two (inclusive) corners
+    fn search_cells(&self, min: Cell, max: Cell, centre: &Vec2, found: &mut Vec<Neighbour>) {
+        for cx in min.0..=max.0 {
+            for cy in min.1..=max.1 {
+                let Some(points) = self.cells.get(&(cx, cy)) else {
+                    continue;
+                };
+                found.extend(points.iter().map(|&i| Neighbour {
+                    index: i,
+                    distance: centre.distance(&self.positions[i]),
+                }));
+            }
+        }
+    }
+}
+
+impl SpatialIndex for GridIndex {
+    fn update(&mut self, positions: &[Vec2]) {
+        if positions.len() == self.positions.len() {
+            // Same points as before, so only shuffle around the ones that changed cell
+            for (i, pos) in positions.iter().enumerate() {
+                let new_cell = self.cell(pos);
+                let old_cell = self.point_cells[i];
+                if new_cell == old_cell {
+                    continue;
+                }
+
+                let old_points = self.cells.get_mut(&old_cell).unwrap();
+                let slot = old_points.iter().position(|&j| j == i).unwrap();
+                old_points.swap_remove(slot);
+                if old_points.is_empty() {
+                    self.cells.remove(&old_cell);
+                }
+
+                self.cells.entry(new_cell).or_default().push(i);
+                self.point_cells[i] = new_cell;
+            }
+        } else {
+            // Points have been added or removed, so the indices have all shifted
+            self.rebuild(positions);
+        }
+
+        self.positions = positions.to_vec();
+    }
+
+    fn within(&self, centre: &Vec2, radius: f32) -> Vec<Neighbour> {
+        let min = self.cell(&centre.add_scalar(-radius));
+        let max = self.cell(&centre.add_scalar(radius));
+
+        let mut found = vec![];
+        self.search_cells(min, max, centre, &mut found);
+        found.retain(|n| n.distance <= radius);
+        found.sort_by_key(|n| n.index);
+
+        found
+    }
+
+    fn nearest(&self, centre: &Vec2, k: usize) -> Vec<Neighbour> {
+        if k == 0 || self.positions.is_empty() {
+            return vec![];
+        }
+
+        // Search outwards one ring of cells at a time. Once we've got k points, anything in the
+        // next ring out is at least `rings * cell_size` away, so we can stop once the kth closest
+        // is nearer than that.
+        let (cx, cy) = self.cell(centre);
+        let mut found = vec![];
+        let mut ring = 0;
+        loop {
+            if ring == 0 {
+                self.search_cells((cx, cy), (cx, cy), centre, &mut found);
+            } else {
+                // Top & bottom rows, then the left & right columns without the corners
+                self.search_cells(
+                    (cx - ring, cy - ring),
+                    (cx + ring, cy - ring),
+                    centre,
+                    &mut found,
+                );
+                self.search_cells(
+                    (cx - ring, cy + ring),
+                    (cx + ring, cy + ring),
+                    centre,
+                    &mut found,
+                );
+                self.search_cells(
+                    (cx - ring, cy - ring + 1),
+                    (cx - ring, cy + ring - 1),
+                    centre,
+                    &mut found,
+                );
+                self.search_cells(
+                    (cx + ring, cy - ring + 1),
+                    (cx + ring, cy + ring - 1),
+                    centre,
+                    &mut found,
+                );
+            }
+
+            if found.len() >= k || found.len() == self.positions.len() {
+                sort_by_distance(&mut found);
+                let safe_distance = ring as f32 * self.cell_size;
+                if found.len() == self.positions.len() || found[k - 1].distance <= safe_distance {
+                    found.truncate(k);
+                    return found;
+                }
+            }
+            ring += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    fn random_positions(rng: &mut impl Rng, n: usize) -> Vec<Vec2> {
+        (0..n)
+            .map(|_| Vec2::new(rng.gen_range(-50. ..50.), rng.gen_range(-50. ..50.)))
+            .collect()
+    }
+
+    fn brute_force_within(positions: &[Vec2], centre: &Vec2, radius: f32) -> Vec<Neighbour> {
+        positions
+            .iter()
+            .enumerate()
+            .map(|(index, pos)| Neighbour {
+                index,
+                distance: centre.distance(pos),
+            })
+            .filter(|n| n.distance <= radius)
+            .collect()
+    }
+
+    #[test]
+    fn backends_match_brute_force() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut positions = random_positions(&mut rng, 500);
+
+        let mut kdtree = SpatialIndexConfig::KdTree.build(&positions);
+        let mut grid = SpatialIndexConfig::Grid { cell_size: 7. }.build(&positions);
+
+        for _ in 0..5 {
+            for centre in positions.iter().take(50) {
+                let expected = brute_force_within(&positions, centre, 6.);
+                assert_eq!(kdtree.within(centre, 6.), expected);
+                assert_eq!(grid.within(centre, 6.), expected);
+
+                let mut expected_nearest = brute_force_within(&positions, centre, f32::INFINITY);
+                sort_by_distance(&mut expected_nearest);
+                expected_nearest.truncate(8);
+                assert_eq!(kdtree.nearest(centre, 8), expected_nearest);
+                assert_eq!(grid.nearest(centre, 8), expected_nearest);
+            }
+
+            // Jiggle everything about & drop a few points to exercise both update paths
+            positions.iter_mut().for_each(|pos| {
+                *pos = pos.add(&Vec2::new(rng.gen_range(-5. ..5.), rng.gen_range(-5. ..5.)))
+            });
+            kdtree.update(&positions);
+            grid.update(&positions);
+
+            positions.truncate(positions.len() - 10);
+            kdtree.update(&positions);
+            grid.update(&positions);
+        }
+    }
+
+    #[test]
+    fn nearest_with_too_few_points() {
+        let positions = vec![Vec2::new(0., 0.), Vec2::new(100., 0.)];
+        let grid = SpatialIndexConfig::Grid { cell_size: 1. }.build(&positions);
+        let kdtree = SpatialIndexConfig::KdTree.build(&positions);
+
+        assert_eq!(grid.nearest(&Vec2::new(1., 0.), 5).len(), 2);
+        assert_eq!(kdtree.nearest(&Vec2::new(1., 0.), 5).len(), 2);
+    }
+}
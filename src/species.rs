@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::SpeciesConfig;
+
+/// Index of a species in the world's [SpeciesTable]
+pub type SpeciesId = usize;
+
+/// Everything the simulation needs to know about a species at runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Species {
+    pub name: String,
+    pub colour: [u8; 3],
+}
+
+/// All the species in the world, along with who eats whom
+#[derive(Debug, Clone)]
+pub struct SpeciesTable {
+    species: Vec<Species>,
+    // diet[predator][prey] is true if the predator eats the prey
+    diet: Vec<Vec<bool>>,
+}
+
+impl SpeciesTable {
+    /// Build the table from the scenario's species. Assumes the config has already been validated.
+    pub fn from_config(configs: &[SpeciesConfig]) -> Self {
+        let species = configs
+            .iter()
+            .map(|config| Species {
+                name: config.name.clone(),
+                colour: config.colour,
+            })
+            .collect::<Vec<_>>();
+
+        let diet = configs
+            .iter()
+            .map(|predator| {
+                configs
+                    .iter()
+                    .map(|prey| predator.eats.contains(&prey.name))
+                    .collect()
+            })
+            .collect();
+
+        Self { species, diet }
+    }
+
+    pub fn get(&self, id: SpeciesId) -> &Species {
+        &self.species[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.species.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.species.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SpeciesId, &Species)> {
+        self.species.iter().enumerate()
+    }
+
+    /// Does the predator species gobble up the prey species?
+    pub fn eats(&self, predator: SpeciesId, prey: SpeciesId) -> bool {
+        self.diet[predator][prey]
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{boi::Weights, world::World};
+
+/// Numbers for the whole world after a single tick
+#[derive(Debug, Clone, Serialize)]
+pub struct TickStats {
+    pub tick: u64,
+    pub food: Option<f32>, // Total food biomass, if there's a food layer
+    pub species: Vec<SpeciesStats>, // In the same order as the world's species
+}
+
+/// Numbers for one species after a single tick. Means are None if there's nobody left to average.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeciesStats {
+    pub species: String,
+    pub population: usize,
+    pub eaten: usize,   // How many of this species got gobbled during the tick
+    pub starved: usize, // How many of this species ran out of energy during the tick
+    pub born: usize,    // How many of this species were born during the tick
+    pub mean_speed: Option<f32>,
+    pub mean_vision: Option<f32>,
+    pub mean_turning_speed: Option<f32>,
+    pub mean_energy: Option<f32>,
+    pub mean_weights: Option<Weights>, // How much they care about each rule, on average
+    pub mean_nearest_neighbour: Option<f32>, // Distance to the closest other boi of any species
+}
+
+impl TickStats {
+    /// Work out the stats for the world as it is now. Meant to be called right after a step, so
+    /// the kills line up.
+    pub fn collect(world: &World) -> Self {
+        let species = world
+            .species
+            .iter()
+            .map(|(id, species)| {
+                let members = world
+                    .bois
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, boi)| boi.species == id)
+                    .collect::<Vec<_>>();
+                let mean = |value: &dyn Fn(usize) -> Option<f32>| {
+                    let values = members
+                        .iter()
+                        .filter_map(|(i, _)| value(*i))
+                        .collect::<Vec<_>>();
+                    (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+                };
+
+                SpeciesStats {
+                    species: species.name.clone(),
+                    population: members.len(),
+                    eaten: world
+                        .kills
+                        .iter()
+                        .filter(|kill| kill.prey_species == id)
+                        .count(),
+                    starved: world
+                        .starved
+                        .iter()
+                        .filter(|starvation| starvation.species == id)
+                        .count(),
+                    born: world
+                        .births
+                        .iter()
+                        .filter(|birth| birth.species == id)
+                        .count(),
+                    mean_speed: mean(&|i| Some(world.bois[i].speed)),
+                    mean_vision: mean(&|i| Some(world.bois[i].vision)),
+                    mean_turning_speed: mean(&|i| Some(world.bois[i].turning_speed)),
+                    mean_energy: mean(&|i| Some(world.bois[i].energy)),
+                    mean_weights: Weights::mean(members.iter().map(|(_, boi)| boi.weights)),
+                    mean_nearest_neighbour: mean(&|i| {
+                        // Closest will be the boi itself
+                        world
+                            .boi_index
+                            .nearest(&world.bois[i].position, 2)
+                            .into_iter()
+                            .find(|n| n.index != i)
+                            .map(|n| n.distance)
+                    }),
+                }
+            })
+            .collect();
+
+        Self {
+            tick: world.tick,
+            food: world.food.as_ref().map(|food| food.total()),
+            species,
+        }
+    }
+}
+
+/// File format for [StatsWriter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Csv,       // One row per species per tick, with world-wide numbers repeated on each
+    JsonLines, // One object per tick
+}
+
+impl StatsFormat {
+    /// Pick a format based on the file extension, defaulting to CSV
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") | Some("json") => Self::JsonLines,
+            _ => Self::Csv,
+        }
+    }
+}
+
+/// Writes stats out as they're collected, for plotting elsewhere
+pub struct StatsWriter {
+    out: BufWriter<fs::File>,
+    format: StatsFormat,
+}
+
+impl StatsWriter {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let format = StatsFormat::from_path(path.as_ref());
+        let mut out = BufWriter::new(fs::File::create(path)?);
+        if format == StatsFormat::Csv {
+            writeln!(
+                out,
+                "tick,species,population,eaten,starved,born,mean_speed,mean_vision,mean_turning_speed,mean_energy,mean_separation,mean_alignment,mean_cohesion,mean_attack,mean_defend,mean_nearest_neighbour,food"
+            )?;
+        }
+
+        Ok(Self { out, format })
+    }
+
+    pub fn write(&mut self, stats: &TickStats) -> io::Result<()> {
+        match self.format {
+            StatsFormat::Csv => {
+                let optional =
+                    |value: Option<f32>| value.map(|v| v.to_string()).unwrap_or_default();
+                for species in &stats.species {
+                    writeln!(
+                        self.out,
+                        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                        stats.tick,
+                        csv_field(&species.species),
+                        species.population,
+                        species.eaten,
+                        species.starved,
+                        species.born,
+                        optional(species.mean_speed),
+                        optional(species.mean_vision),
+                        optional(species.mean_turning_speed),
+                        optional(species.mean_energy),
+                        optional(species.mean_weights.map(|w| w.separation)),
+                        optional(species.mean_weights.map(|w| w.alignment)),
+                        optional(species.mean_weights.map(|w| w.cohesion)),
+                        optional(species.mean_weights.map(|w| w.attack)),
+                        optional(species.mean_weights.map(|w| w.defend)),
+                        optional(species.mean_nearest_neighbour),
+                        optional(stats.food),
+                    )?;
+                }
+            }
+            StatsFormat::JsonLines => {
+                serde_json::to_writer(&mut self.out, stats)?;
+                writeln!(self.out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Make sure everything's made it to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Quote a CSV field if it needs it
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn counts_add_up() {
+        let mut config = Config::default();
+        config.world.num_bois = 300;
+        config.world.arena_radius = 30.;
+
+        let mut world = World::new(&config, 11);
+        let mut eaten = 0;
+        for _ in 0..60 {
+            world.step(1. / 30.);
+            let stats = TickStats::collect(&world);
+
+            assert_eq!(stats.tick, world.tick);
+            assert_eq!(
+                stats.species.iter().map(|s| s.population).sum::<usize>(),
+                world.bois.len()
+            );
+            eaten += stats.species.iter().map(|s| s.eaten).sum::<usize>();
+        }
+        // Nobody breeds or starves by default
+        assert_eq!(world.bois.len() + eaten, 300);
+
+        let prey = &TickStats::collect(&world).species[1];
+        let (min, max) = (config.species[1].speed.min, config.species[1].speed.max);
+        assert!((min..max).contains(&prey.mean_speed.unwrap()));
+        assert!(prey.mean_nearest_neighbour.unwrap() > 0.);
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("prey"), "prey");
+        assert_eq!(csv_field("big, \"bad\" wolf"), "\"big, \"\"bad\"\" wolf\"");
+    }
+}
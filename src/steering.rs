@@ -0,0 +1,481 @@
+//! The rules bois steer by. Each one looks at what a boi can perceive & suggests a direction to go
+//! in, along with how much it counts, and the boi heads for the sum of them all. Which rules a
+//! species follows, and in what order, comes from the scenario.
+
+use std::{fmt, ops::Sub};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{RuleWeights, SpeciesConfig},
+    perception::{Neighbour, Perception},
+    strategy::Strategy,
+    vec::Vec2,
+};
+
+/// A single behaviour, eg. flocking together or running away
+pub trait SteeringRule: Send + Sync {
+    /// Direction to steer in & how much it counts, or None if the rule has nothing to say
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)>;
+}
+
+/// Names of the built in rules, as they're written in the scenario file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    Separation,
+    Alignment,
+    Cohesion,
+    Forage,
+    Escape,
+    Attack,
+    Defend,
+    Avoid,
+}
+
+impl RuleKind {
+    /// Every built in rule, in the order they're added up in unless a species says otherwise
+    pub const ALL: [RuleKind; 8] = [
+        RuleKind::Separation,
+        RuleKind::Alignment,
+        RuleKind::Cohesion,
+        RuleKind::Forage,
+        RuleKind::Escape,
+        // Chasing food & running away are both top priority
+        RuleKind::Attack,
+        RuleKind::Defend,
+        // Not crashing is even more important
+        RuleKind::Avoid,
+    ];
+
+    /// Make the rule, with the weights from [rules] for anything that isn't per boi
+    pub fn build(self, rules: &RuleWeights) -> Box<dyn SteeringRule> {
+        match self {
+            RuleKind::Separation => Box::new(Separation),
+            RuleKind::Alignment => Box::new(Alignment),
+            RuleKind::Cohesion => Box::new(Cohesion),
+            RuleKind::Forage => Box::new(Forage {
+                weight: rules.forage,
+            }),
+            RuleKind::Escape => Box::new(Escape {
+                exponent: rules.escape_exponent,
+            }),
+            RuleKind::Attack => Box::new(Attack),
+            RuleKind::Defend => Box::new(Defend),
+            RuleKind::Avoid => Box::new(Avoid {
+                weight: rules.avoid,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RuleKind::Separation => "separation",
+            RuleKind::Alignment => "alignment",
+            RuleKind::Cohesion => "cohesion",
+            RuleKind::Forage => "forage",
+            RuleKind::Escape => "escape",
+            RuleKind::Attack => "attack",
+            RuleKind::Defend => "defend",
+            RuleKind::Avoid => "avoid",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The built in strategy: follow a list of rules & head for the sum of what they say
+pub struct Steering {
+    pub rules: Vec<Box<dyn SteeringRule>>,
+    pub flow_compensation: f32, // See [SpeciesConfig::flow_compensation]
+}
+
+impl Steering {
+    pub fn from_config(species: &SpeciesConfig, rules: &RuleWeights) -> Self {
+        Self {
+            rules: species
+                .steering
+                .iter()
+                .map(|rule| rule.build(rules))
+                .collect(),
+            flow_compensation: species.flow_compensation,
+        }
+    }
+}
+
+impl Strategy for Steering {
+    fn decide(&self, perception: &Perception) -> Vec2 {
+        let heading = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.steer(perception))
+            .map(|(direction, weight)| direction.mul(weight))
+            .reduce(|a, b| a.add(&b))
+            // If there's no signal, keep on truckin'
+            .unwrap_or_else(|| perception.direction_vector());
+
+        // Lean into the wind, so the flow plus where we're going adds up to where we want to be
+        if self.flow_compensation > 0. {
+            heading
+                .normalise()
+                .mul(perception.speed)
+                .sub(&perception.environment.flow.mul(self.flow_compensation))
+        } else {
+            heading
+        }
+    }
+}
+
+/// Nearest visible boi of another species that matches `filter`
+fn nearest_enemy(
+    perception: &Perception,
+    filter: impl Fn(&Neighbour) -> bool,
+) -> Option<&Neighbour> {
+    perception
+        .neighbours
+        .iter()
+        .filter(|neighbour| neighbour.species != perception.species && filter(neighbour))
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+/// Steer away from nearby friends - each one counts for 1 / distance
+pub struct Separation;
+
+impl SteeringRule for Separation {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        perception
+            .friends()
+            .map(|friend| {
+                Vec2::new(0., 0.)
+                    .sub(&friend.offset)
+                    .normalise()
+                    .div(friend.distance)
+            })
+            .reduce(|a, b| a.add(&b))
+            .map(|v| (v.normalise(), perception.weights.separation))
+    }
+}
+
+/// Line up with the average direction of nearby friends
+pub struct Alignment;
+
+impl SteeringRule for Alignment {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        perception
+            .friends()
+            .map(|friend| Vec2::new(friend.heading.cos(), friend.heading.sin()))
+            .reduce(|a, b| a.add(&b))
+            .map(|v| (v.normalise(), perception.weights.alignment))
+    }
+}
+
+/// Steer towards the centre of gravity of nearby friends
+pub struct Cohesion;
+
+impl SteeringRule for Cohesion {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        // Averaged over where they are in the world rather than their offsets, since the two round
+        // differently & this is how it's always been worked out
+        let count = perception.friends().count() as f32;
+        perception
+            .friends()
+            .map(|friend| friend.position.div(count))
+            .reduce(|a, b| a.add(&b))
+            .map(|centre_of_gravity| {
+                (
+                    centre_of_gravity.sub(&perception.position).normalise(),
+                    perception.weights.cohesion,
+                )
+            })
+    }
+}
+
+/// Grazers steer towards wherever there's more food in sight
+pub struct Forage {
+    pub weight: f32,
+}
+
+impl SteeringRule for Forage {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        perception
+            .environment
+            .food
+            .map(|scent| (scent, self.weight))
+    }
+}
+
+/// Don't escape the arena - steer towards the centre once we're past the edge. Weight ramps up
+/// as (distance past the edge) ^ exponent.
+pub struct Escape {
+    pub exponent: f32,
+}
+
+impl SteeringRule for Escape {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        let boundary = &perception.boundary;
+        (boundary.distance > boundary.radius).then(|| {
+            (
+                boundary.centre.normalise(),
+                boundary
+                    .distance
+                    .sub(boundary.radius)
+                    .max(0.)
+                    .powf(self.exponent),
+            )
+        })
+    }
+}
+
+/// Steer towards the nearest boi we can eat
+pub struct Attack;
+
+impl SteeringRule for Attack {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        nearest_enemy(perception, |enemy| enemy.prey)
+            .map(|prey| (prey.offset.normalise(), perception.weights.attack))
+    }
+}
+
+/// Steer away from the nearest boi that can eat us
+pub struct Defend;
+
+impl SteeringRule for Defend {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        nearest_enemy(perception, |enemy| enemy.predator).map(|predator| {
+            (
+                Vec2::new(0., 0.).sub(&predator.offset).normalise(),
+                perception.weights.defend,
+            )
+        })
+    }
+}
+
+/// Steer away from any obstacle we're about to run into. Ramps up from nothing at the edge of our
+/// vision.
+pub struct Avoid {
+    pub weight: f32,
+}
+
+impl SteeringRule for Avoid {
+    fn steer(&self, perception: &Perception) -> Option<(Vec2, f32)> {
+        perception.boundary.obstacle.map(|hit| {
+            (
+                hit.normal.mul(1. - hit.distance / perception.vision),
+                self.weight,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        boi::{Boi, Weights},
+        config::Config,
+        perception::{Boundary, Environment},
+        world::World,
+    };
+    use std::path::Path;
+
+    // We're at the origin, so offsets & positions are the same
+    fn neighbour(species: usize, x: f32, y: f32) -> Neighbour {
+        let offset = Vec2::new(x, y);
+        Neighbour {
+            offset,
+            position: offset,
+            distance: offset.distance(&Vec2::new(0., 0.)),
+            heading: 0.,
+            species,
+            prey: false,
+            predator: species == 0,
+        }
+    }
+
+    // Prey facing along the x axis, with a friend above & a predator below
+    fn perception() -> Perception {
+        Perception {
+            species: 1,
+            position: Vec2::new(0., 0.),
+            heading: 0.,
+            speed: 2.,
+            vision: 10.,
+            weights: Weights::from_rules(&RuleWeights::default()),
+            neighbours: vec![neighbour(1, 0., -3.), neighbour(0, 0., 3.)],
+            boundary: Boundary {
+                centre: Vec2::new(0., 0.),
+                distance: 0.,
+                radius: 100.,
+                obstacle: None,
+            },
+            environment: Environment {
+                flow: Vec2::new(0., 1.),
+                food: None,
+            },
+        }
+    }
+
+    fn steering(rules: &[RuleKind], flow_compensation: f32) -> Steering {
+        let species = SpeciesConfig {
+            steering: rules.to_vec(),
+            flow_compensation,
+            ..Default::default()
+        };
+        Steering::from_config(&species, &RuleWeights::default())
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(&b) < 1e-5
+    }
+
+    #[test]
+    fn species_only_follow_their_own_rules() {
+        // Only running away counts, straight up
+        let heading = steering(&[RuleKind::Defend], 0.).decide(&perception());
+        assert!(close(heading, Vec2::new(0., -5.)), "{:?}", heading);
+
+        // Only sticking together counts, straight up as well
+        let heading = steering(&[RuleKind::Cohesion], 0.).decide(&perception());
+        assert!(close(heading, Vec2::new(0., -1.)), "{:?}", heading);
+
+        // Nothing to go on
+        assert_eq!(steering(&[], 0.).decide(&perception()), Vec2::new(1., 0.));
+    }
+
+    /// How bois decided where to go before the rules were split out, kept to check the rules still
+    /// add up to exactly the same thing
+    fn monolithic_decide(world: &World, boi: &Boi) -> Vec2 {
+        let modifiers = world.biomes.modifiers(&boi.position, boi.species);
+        let vision = boi.vision * modifiers.vision;
+
+        let nearbois = boi
+            .look(world, vision)
+            .into_iter()
+            .filter(|(_, _, hidden)| !hidden)
+            .map(|(other, distance, _)| (other, distance))
+            .collect::<Vec<_>>();
+        let (friends, enemies): (Vec<_>, Vec<_>) = nearbois
+            .into_iter()
+            .partition(|(other, _)| other.species == boi.species);
+        let (friends, friend_distances): (Vec<_>, Vec<_>) = friends.into_iter().unzip();
+        let (enemies, enemy_distances): (Vec<_>, Vec<_>) = enemies.into_iter().unzip();
+
+        let separation = friends
+            .iter()
+            .map(|other| boi.position.sub(&other.position).normalise())
+            .zip(&friend_distances)
+            .map(|(other, distance)| other.div(*distance))
+            .reduce(|a, b| a.add(&b))
+            .map(|v| v.normalise());
+        let alignment = friends
+            .iter()
+            .map(|other| other.direction_vector())
+            .reduce(|a, b| a.add(&b))
+            .map(|v| v.normalise());
+        let cohesion = friends
+            .iter()
+            .map(|other| other.position.div(friends.len() as f32))
+            .reduce(|a, b| a.add(&b))
+            .map(|centre_of_gravity| centre_of_gravity.sub(&boi.position).normalise());
+        let attack = enemy_distances
+            .iter()
+            .zip(&enemies)
+            .filter(|(_, other)| world.species.eats(boi.species, other.species))
+            .min_by(|(distance1, _), (distance2, _)| distance1.total_cmp(distance2))
+            .map(|(_, other)| other.position.sub(&boi.position).normalise());
+        let defend = enemy_distances
+            .iter()
+            .zip(&enemies)
+            .filter(|(_, other)| world.species.eats(other.species, boi.species))
+            .min_by(|(distance1, _), (distance2, _)| distance1.total_cmp(distance2))
+            .map(|(_, other)| boi.position.sub(&other.position).normalise());
+        let distance_to_centre = world.arena_centre.distance(&boi.position);
+        let escape = (distance_to_centre > world.arena_radius)
+            .then(|| world.arena_centre.sub(&boi.position).normalise());
+        let avoid = world
+            .obstacles
+            .raycast(&boi.position, &boi.direction_vector(), vision)
+            .map(|hit| hit.normal.mul(1. - hit.distance / vision));
+        let forage = world
+            .food
+            .as_ref()
+            .filter(|food| food.grazes(boi.species))
+            .and_then(|food| food.scent(&boi.position, vision));
+
+        let heading = [
+            separation.map(|x| x.mul(boi.weights.separation)),
+            alignment.map(|x| x.mul(boi.weights.alignment)),
+            cohesion.map(|x| x.mul(boi.weights.cohesion)),
+            forage.map(|x| x.mul(world.rules.forage)),
+            escape.map(|x| {
+                x.mul(
+                    distance_to_centre
+                        .sub(world.arena_radius)
+                        .max(0.)
+                        .powf(world.rules.escape_exponent),
+                )
+            }),
+            attack.map(|x| x.mul(boi.weights.attack)),
+            defend.map(|x| x.mul(boi.weights.defend)),
+            avoid.map(|x| x.mul(world.rules.avoid)),
+        ]
+        .into_iter()
+        .flatten()
+        .reduce(|a, b| a.add(&b))
+        .unwrap_or_else(|| boi.direction_vector());
+
+        let compensation = world.config.species[boi.species].flow_compensation;
+        if compensation > 0. {
+            let flow = world.flow.at(&boi.position, world.time());
+            heading
+                .normalise()
+                .mul(boi.speed * modifiers.speed)
+                .sub(&flow.mul(compensation))
+        } else {
+            heading
+        }
+    }
+
+    #[test]
+    fn rules_match_the_old_monolithic_decide() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        let mut scenarios = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect::<Vec<_>>();
+        scenarios.sort();
+
+        for path in scenarios {
+            let mut config = Config::load(&path).unwrap();
+            // Brains never went through the old code
+            if config.species.iter().any(|species| species.brain.is_some()) {
+                continue;
+            }
+            config.world.num_bois = config.world.num_bois.min(200);
+
+            let mut world = World::new(&config, 3);
+            for tick in 0..300 {
+                for boi in &world.bois {
+                    assert_eq!(
+                        world.decide(boi),
+                        monolithic_decide(&world, boi),
+                        "{} tick {} boi {}",
+                        path.display(),
+                        tick,
+                        boi.id
+                    );
+                }
+                world.step(1. / config.world.tick_rate as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn leaning_into_the_flow() {
+        // Flow pushes down at 1, so we aim up a bit to keep going along x at full speed
+        let heading = steering(&[], 1.).decide(&perception());
+        assert!(close(heading, Vec2::new(2., -1.)), "{:?}", heading);
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -65,4 +67,9 @@ impl Vec2 {
     pub fn direction_radians(&self) -> f32 {
         self.y.atan2(self.x)
     }
+
+    /// Linear interpolation, t=0 gives self & t=1 gives other
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        self.add(&other.sub(self).mul(t))
+    }
 }
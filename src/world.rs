@@ -0,0 +1,527 @@
+use crate::{
+    biome::Biomes,
+    boi::{Boi, BoiTemplate, Sampled, Weights},
+    brain::{Brain, BrainConfig},
+    breeding::Birth,
+    config::{Config, RuleWeights},
+    entity::{EntityId, WeightedTemplate},
+    flow::Flow,
+    food::Food,
+    nest::Nest,
+    obstacle::Obstacles,
+    par,
+    perception::Perception,
+    spatial::SpatialIndex,
+    species::{SpeciesId, SpeciesTable},
+    steering::Steering,
+    strategy::Strategy,
+    vec::Vec2,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    iter,
+};
+
+use rand::{distributions::Uniform, prelude::*};
+use rand_chacha::ChaCha8Rng;
+
+/// RNG used for everything in the simulation. Seedable so runs can be reproduced.
+pub type SimRng = ChaCha8Rng;
+
+/// Nest that all the bois in the world hatch from
+pub type BoiNest = Nest<SimRng, Uniform<f32>, WeightedTemplate<BoiTemplate<Uniform<f32>>>>;
+
+/// Places a boi can try hatching before it's left wherever it ended up. Only matters if obstacles
+/// overlap enough to trap it.
+const SPAWN_TRIES: usize = 100;
+
+/// Record of one boi gobbling up another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Kill {
+    pub predator: EntityId,
+    pub prey: EntityId,
+    pub prey_species: SpeciesId,
+}
+
+/// Record of a boi that ran out of energy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Starvation {
+    pub boi: EntityId,
+    pub species: SpeciesId,
+}
+
+/// The simulation itself, with no knowledge of how (or if) it's being rendered
+pub struct World {
+    pub bois: Vec<Boi>,
+    pub boi_index: Box<dyn SpatialIndex>, // Indices line up with `bois` between steps
+    pub arena_centre: Vec2,
+    pub arena_radius: f32,
+    pub kill_radius: f32,
+    pub obstacles: Obstacles,
+    pub biomes: Biomes,
+    pub flow: Flow,
+    pub food: Option<Food>,
+    pub species: SpeciesTable,
+    pub rules: RuleWeights,
+    pub strategies: Vec<Box<dyn Strategy>>, // How each species decides where to go, by species
+    pub kills: Vec<Kill>,                   // Who got gobbled during the last step
+    pub starved: Vec<Starvation>,           // Who ran out of energy during the last step
+    pub births: Vec<Birth>,                 // Who was born during the last step
+    pub nest: BoiNest,                      // Holds the master RNG, seeded once at the start
+    pub tick: u64,                          // Number of steps taken so far
+    pub config: Config, // Scenario the world was built from, kept around for snapshots
+}
+
+impl World {
+    /// Create a new world. The same seed will always produce the same simulation.
+    pub fn new(config: &Config, seed: u64) -> Self {
+        // Spawn a bunch of Bois
+        let mut nest = build_nest(config, SimRng::seed_from_u64(seed), 0);
+        let mut bois = (0..config.world.num_bois)
+            .map(|_| nest.spawn())
+            .collect::<Vec<_>>();
+
+        // Nobody hatches inside a rock. If there's no getting out, try somewhere else.
+        let obstacles = Obstacles::from_config(&config.obstacles);
+        for boi in &mut bois {
+            let spawned = boi.position;
+            boi.position = iter::once(spawned)
+                .chain(iter::repeat_with(|| nest.position()))
+                .take(SPAWN_TRIES)
+                .find_map(|position| obstacles.push_out(&position))
+                .unwrap_or(spawned);
+        }
+
+        Self::from_parts(config, bois, nest, 0)
+    }
+
+    /// Put a world back together from the things that change as it runs
+    pub fn from_parts(config: &Config, bois: Vec<Boi>, nest: BoiNest, tick: u64) -> Self {
+        let boi_index = config.world.spatial_index.build(&positions(&bois));
+        let arena_centre = Vec2 { x: 0., y: 0. };
+        let species_names = config
+            .species
+            .iter()
+            .map(|s| s.name.clone())
+            .collect::<Vec<_>>();
+
+        Self {
+            bois,
+            boi_index,
+            arena_centre,
+            arena_radius: config.world.arena_radius,
+            kill_radius: config.world.kill_radius,
+            obstacles: Obstacles::from_config(&config.obstacles),
+            biomes: Biomes::from_config(&config.biomes, &species_names),
+            flow: Flow::from_config(&config.flow),
+            food: config.food.as_ref().map(|food| {
+                Food::new(
+                    food,
+                    &species_names,
+                    &arena_centre,
+                    config.world.arena_radius,
+                )
+            }),
+            species: SpeciesTable::from_config(&config.species),
+            rules: config.rules.clone(),
+            strategies: config
+                .species
+                .iter()
+                .map(|species| -> Box<dyn Strategy> {
+                    match &species.brain {
+                        Some(BrainConfig::Genome(genome)) => Box::new(Brain::new(genome)),
+                        _ => Box::new(Steering::from_config(species, &config.rules)),
+                    }
+                })
+                .collect(),
+            kills: vec![],
+            starved: vec![],
+            births: vec![],
+            nest,
+            tick,
+            config: config.clone(),
+        }
+    }
+
+    /// Seconds of simulated time so far
+    pub fn time(&self) -> f32 {
+        self.tick as f32 / self.config.world.tick_rate as f32
+    }
+
+    /// Where a boi wants to go, going only on what it can perceive
+    pub fn decide(&self, boi: &Boi) -> Vec2 {
+        self.strategies[boi.species].decide(&Perception::of(self, boi))
+    }
+
+    /// Advance the simulation by one tick of `dt` seconds
+    pub fn step(&mut self, dt: f32) {
+        self.step_with(dt, &HashMap::new());
+    }
+
+    /// Advance the simulation by one tick of `dt` seconds, with some of the bois being told which
+    /// direction to go in instead of deciding for themselves
+    pub fn step_with(&mut self, dt: f32, overrides: &HashMap<EntityId, Vec2>) {
+        // Step 1) decision time
+        let decisions = par::map(&self.bois, |boi| match overrides.get(&boi.id) {
+            Some(direction) => *direction,
+            None => self.decide(boi),
+        });
+
+        // Step 2) apply the decisions
+        let modifiers = self
+            .bois
+            .iter()
+            .map(|boi| self.biomes.modifiers(&boi.position, boi.species))
+            .collect::<Vec<_>>();
+        let turns = self
+            .bois
+            .iter_mut()
+            .zip(decisions)
+            .zip(&modifiers)
+            .map(|((boi, new_direction), modifiers)| {
+                let old_direction = boi.direction;
+                // Turning slower is the same as having less time to turn in
+                boi.turn_towards(dt * modifiers.turning_speed, &new_direction);
+                (boi.direction - old_direction).abs()
+            })
+            .collect::<Vec<_>>();
+
+        // Step 3) Advance time, getting carried along by any wind or current on the way
+        let time = self.time();
+        self.bois
+            .iter_mut()
+            .zip(turns)
+            .zip(modifiers)
+            .for_each(|((boi, turned), modifiers)| {
+                let speed = boi.speed * modifiers.speed;
+                let drift = self.flow.at(&boi.position, time).mul(dt);
+                // Stay put rather than end up stuck inside an obstacle
+                boi.position = self
+                    .obstacles
+                    .push_out(
+                        &boi.position
+                            .add(&boi.direction_vector().mul(speed * dt))
+                            .add(&drift),
+                    )
+                    .unwrap_or(boi.position);
+                boi.age += dt;
+
+                // Moving about is hard work
+                let energy = &self.config.species[boi.species].energy;
+                boi.energy = (boi.energy + energy.regen * dt
+                    - energy.move_cost * speed * dt
+                    - energy.turn_cost * turned)
+                    .min(energy.max);
+            });
+
+        // Grazers eat whatever's underneath them, then the food grows back a bit
+        if let Some(food) = &mut self.food {
+            for boi in self.bois.iter_mut() {
+                if !food.grazes(boi.species) {
+                    continue;
+                }
+                let eaten = food.eat(&boi.position, food.config.bite * dt);
+                let max = self.config.species[boi.species].energy.max;
+                boi.energy = (boi.energy + eaten * food.config.nutrition).min(max);
+            }
+            food.grow(dt);
+        }
+
+        // Step 4) Apply consequences (Eg. bois being gobbled)
+        self.boi_index.update(&positions(&self.bois));
+
+        // Figure out which bois got caught
+        self.kills = par::filter_map(&self.bois, |boi| {
+            // Check if there's anything nearby that eats us
+            self.boi_index
+                // Query the spatial index since it's quicker
+                .within(&boi.position, self.kill_radius)
+                .into_iter()
+                // Get the bois based on the spatial query
+                .map(|n| self.bois.get(n.index).expect("Got invalid boi index!"))
+                // Skip ourselves
+                .filter(|boi2| boi2.id != boi.id)
+                // Find the first thing that eats us
+                .find(|boi2| self.species.eats(boi2.species, boi.species))
+                .map(|predator| Kill {
+                    predator: predator.id,
+                    prey: boi.id,
+                    prey_species: boi.species,
+                })
+        });
+
+        // Predators get fed
+        if !self.kills.is_empty() {
+            let lookup = self
+                .bois
+                .iter()
+                .enumerate()
+                .map(|(i, boi)| (boi.id, i))
+                .collect::<HashMap<_, _>>();
+            for kill in &self.kills {
+                let predator = &mut self.bois[lookup[&kill.predator]];
+                let max = self.config.species[predator.species].energy.max;
+                let nutrition = self.config.species[kill.prey_species].energy.nutrition;
+                predator.energy = (predator.energy + nutrition).min(max);
+            }
+        }
+
+        // Anyone that didn't get eaten but ran out of energy starves
+        let mut dead = self
+            .kills
+            .iter()
+            .map(|kill| kill.prey)
+            .collect::<HashSet<_>>();
+        self.starved = self
+            .bois
+            .iter()
+            .filter(|boi| boi.energy <= 0. && !dead.contains(&boi.id))
+            .map(|boi| Starvation {
+                boi: boi.id,
+                species: boi.species,
+            })
+            .collect();
+        dead.extend(self.starved.iter().map(|starvation| starvation.boi));
+
+        // Kill em off
+        self.bois.retain(|boi| !dead.contains(&boi.id));
+
+        // Keep the spatial index in line with whoever's left
+        if !dead.is_empty() {
+            self.boi_index.update(&positions(&self.bois));
+        }
+
+        // Step 5) New life
+        self.breed();
+        if !self.births.is_empty() {
+            self.boi_index.update(&positions(&self.bois));
+        }
+
+        self.tick += 1;
+    }
+}
+
+/// Set up the nest all the bois hatch from, which hands out IDs starting from `next_id`
+pub fn build_nest(config: &Config, rng: SimRng, next_id: EntityId) -> BoiNest {
+    let arena_radius = config.world.arena_radius;
+    Nest {
+        rng,
+        pos: Uniform::new(-arena_radius, arena_radius),
+        direction: Uniform::new(0., 2. * PI),
+        // Each species has its own template
+        template: WeightedTemplate(
+            config
+                .species
+                .iter()
+                .enumerate()
+                .map(|(id, species)| {
+                    let template = BoiTemplate {
+                        species: id,
+                        speed: species.speed.uniform(),
+                        vision: species.vision.uniform(),
+                        fov: species.fov.map(|fov| fov.to_radians().uniform()),
+                        turning_speed: species.turning_speed.uniform(),
+                        weights: species.weights.zip(Weights::from_rules(&config.rules)).map(
+                            |(range, fixed)| match range {
+                                Some(range) => Sampled::From(range.uniform()),
+                                None => Sampled::Fixed(*fixed),
+                            },
+                        ),
+                        energy: species.energy.initial,
+                    };
+                    (species.spawn_weight, template)
+                })
+                .collect(),
+        ),
+        next_id,
+    }
+}
+
+/// Positions of the bois, in the same order as the bois themselves
+fn positions(bois: &[Boi]) -> Vec<Vec2> {
+    bois.iter().map(|boi| boi.position).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_simulation() {
+        let mut config = Config::default();
+        config.world.arena_radius = 50.;
+        config.world.num_bois = 200;
+
+        let mut world1 = World::new(&config, 42);
+        let mut world2 = World::new(&config, 42);
+
+        for _ in 0..100 {
+            world1.step(1. / 30.);
+            world2.step(1. / 30.);
+
+            let state1 = world1
+                .bois
+                .iter()
+                .map(|boi| (boi.id, boi.position, boi.direction))
+                .collect::<Vec<_>>();
+            let state2 = world2
+                .bois
+                .iter()
+                .map(|boi| (boi.id, boi.position, boi.direction))
+                .collect::<Vec<_>>();
+            assert_eq!(state1, state2);
+        }
+    }
+
+    #[test]
+    fn compensating_for_the_flow() {
+        // Mean distance everyone's been carried downstream, with prey leaning into the current
+        let drift = |compensation: f32| {
+            let mut config = Config::default();
+            config.world.arena_radius = 500.;
+            config.world.num_bois = 100;
+            config.species[1].flow_compensation = compensation;
+            config.flow = vec![crate::flow::FlowConfig {
+                field: crate::flow::FieldConfig::Uniform { velocity: [1., 0.] },
+                gust: None,
+            }];
+
+            let mut world = World::new(&config, 3);
+            let start = world
+                .bois
+                .iter()
+                .map(|boi| (boi.id, boi.position.x))
+                .collect::<HashMap<_, _>>();
+            for _ in 0..90 {
+                world.step(1. / 30.);
+            }
+            let prey = world.bois.iter().filter(|boi| boi.species == 1);
+            prey.clone()
+                .map(|boi| boi.position.x - start[&boi.id])
+                .sum::<f32>()
+                / prey.count() as f32
+        };
+
+        // 3 seconds of drift, give or take where everyone was going anyway
+        let drifting = drift(0.);
+        assert!((drifting - 3.).abs() < 1., "{}", drifting);
+        assert!(drift(1.) < drifting / 2.);
+    }
+
+    #[test]
+    fn grazing() {
+        // Mean prey energy after a few seconds, with or without anything to eat
+        let energy = |food: bool| {
+            let mut config = Config::default();
+            config.world.arena_radius = 30.;
+            config.world.num_bois = 100;
+            config.species[1].energy.initial = 50.;
+            config.species[1].energy.move_cost = 1.;
+            config.food = food.then(|| crate::food::FoodConfig {
+                grazers: vec!["prey".into()],
+                ..Default::default()
+            });
+
+            let mut world = World::new(&config, 5);
+            let start = world.food.as_ref().map(|food| food.total());
+            for _ in 0..60 {
+                world.step(1. / 30.);
+            }
+            if let (Some(start), Some(food)) = (start, &world.food) {
+                assert!(food.total() < start);
+            }
+
+            let prey = world.bois.iter().filter(|boi| boi.species == 1);
+            prey.clone().map(|boi| boi.energy).sum::<f32>() / prey.count() as f32
+        };
+
+        assert!(energy(false) < 50.);
+        assert!(energy(true) > 50.);
+    }
+
+    #[test]
+    fn starving_and_feeding() {
+        let mut config = Config::default();
+        config.world.arena_radius = 30.;
+        config.world.num_bois = 300;
+        // Predators burn through their energy in about a second, unless they eat
+        config.species[0].energy.move_cost = 40.;
+        config.species[1].energy.nutrition = 50.;
+
+        let mut world = World::new(&config, 1);
+        let mut starved = 0;
+        let mut fed = HashSet::new();
+        for _ in 0..60 {
+            world.step(1. / 30.);
+            starved += world.starved.len();
+            fed.extend(world.kills.iter().map(|kill| kill.predator));
+
+            assert!(world.starved.iter().all(|s| s.species == 0));
+            assert!(world.bois.iter().all(|boi| boi.energy > 0.));
+        }
+
+        // Everyone who never ate should be gone
+        let predators = world.bois.iter().filter(|boi| boi.species == 0);
+        assert!(predators.clone().all(|boi| fed.contains(&boi.id)));
+        assert!(starved > 0);
+        // Prey don't do anything energetic
+        assert!(world
+            .bois
+            .iter()
+            .filter(|boi| boi.species == 1)
+            .all(|boi| boi.energy == 100.));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_decisions_match_serial() {
+        let mut config = Config::default();
+        config.world.num_bois = 2000;
+
+        let mut world = World::new(&config, 42);
+        for _ in 0..10 {
+            world.step(1. / 30.);
+
+            let serial = world
+                .bois
+                .iter()
+                .map(|boi| world.decide(boi))
+                .collect::<Vec<_>>();
+            let parallel = par::map(&world.bois, |boi| world.decide(boi));
+            assert_eq!(serial, parallel);
+        }
+    }
+
+    #[test]
+    fn serial_and_parallel_builds_agree() {
+        let mut config = Config::default();
+        config.world.arena_radius = 50.;
+        config.world.num_bois = 500;
+
+        // FNV-1a over everyone's ID, position & direction, & who ate who, every tick
+        let mut hash = 0xcbf29ce484222325u64;
+        let mut add = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        let mut world = World::new(&config, 7);
+        for _ in 0..200 {
+            world.step(1. / 30.);
+            for boi in &world.bois {
+                add(boi.id);
+                add(boi.position.x.to_bits() as u64);
+                add(boi.position.y.to_bits() as u64);
+                add(boi.direction.to_bits() as u64);
+            }
+            for kill in &world.kills {
+                add(kill.predator);
+                add(kill.prey);
+            }
+        }
+
+        // Taken from a serial build, so a parallel build that steps any differently fails here
+        assert_eq!(hash, 3492206801530934722);
+    }
+}